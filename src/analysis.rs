@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+
+use crate::command::{Command, Flash};
+use crate::level::{Attack, RingOrigin};
+use crate::playfield::{Coord, Pattern, Transform};
+use crate::simulation::TICKS_PER_BEAT;
+
+pub const MAX_MOVES_PER_SECOND: f64 = 8.0;
+
+#[derive(Clone, Copy, Debug)]
+pub struct AttackAnalysis<const N: usize, const M: usize> {
+    pub required_moves: Option<usize>,
+    pub worst_start: (Coord<N>, Coord<M>),
+    pub worst_transform: Transform<N, M>,
+}
+
+impl<const N: usize, const M: usize> AttackAnalysis<N, M> {
+    pub fn is_impossible(&self) -> bool {
+        self.required_moves.is_none()
+    }
+
+    pub fn is_frame_perfect(&self, bpm: f64) -> bool {
+        self.required_moves
+            .is_some_and(|moves| moves as f64 * bpm / 60.0 > MAX_MOVES_PER_SECOND)
+    }
+}
+
+fn positions<const N: usize, const M: usize>() -> impl Iterator<Item = (Coord<N>, Coord<M>)> {
+    Coord::iter_all().flat_map(|i| Coord::iter_all().map(move |o| (i, o)))
+}
+
+// The tick each strike lands on, counted from the start of the attack.
+fn strikes<const N: usize, const M: usize>(
+    attack: &Attack<N, M>,
+    transform: Transform<N, M>,
+) -> Vec<(usize, Pattern<N, M>)> {
+    let mut commands = VecDeque::new();
+    attack.clone().enqueue(&mut commands, transform);
+    let mut tick = 0;
+    let mut strikes = Vec::new();
+    for command in commands {
+        if let Command::FlashPattern(pattern, flash, ticks) = command {
+            if flash == Flash::Strike {
+                strikes.push((tick, pattern));
+            }
+            tick += ticks as usize;
+        }
+    }
+    strikes
+}
+
+fn survivable<const N: usize, const M: usize>(
+    strikes: &[(usize, Pattern<N, M>)],
+    start: (Coord<N>, Coord<M>),
+    moves: usize,
+) -> bool {
+    let mut reachable = [[false; N]; M];
+    reachable[start.1.inner()][start.0.inner()] = true;
+    // Moves per beat are spread over the ticks of each beat, so a strike
+    // half a beat after another may leave no time to move at all.
+    let budget = |tick: usize| moves * tick / TICKS_PER_BEAT as usize;
+    let mut last_tick = 0;
+    for &(tick, ref strike) in strikes {
+        // Every tile can be reached in N / 2 + M / 2 moves, so longer gaps
+        // between strikes don't reach any further.
+        let steps = (budget(tick) - budget(last_tick)).min(N / 2 + M / 2);
+        for _ in 0..steps {
+            let previous = reachable;
+            for (i, o) in positions::<N, M>() {
+                reachable[o.inner()][i.inner()] = [
+                    (i, o),
+                    (i - Coord::ONE, o),
+                    (i + Coord::ONE, o),
+                    (i, o - Coord::ONE),
+                    (i, o + Coord::ONE),
+                ]
+                .into_iter()
+                .any(|(i, o)| previous[o.inner()][i.inner()]);
+            }
+        }
+        last_tick = tick;
+        for position in positions::<N, M>() {
+            if strike[position] {
+                reachable[position.1.inner()][position.0.inner()] = false;
+            }
+        }
+        if !reachable.iter().flatten().any(|&r| r) {
+            return false;
+        }
+    }
+    true
+}
+
+fn required_moves<const N: usize, const M: usize>(
+    strikes: &[(usize, Pattern<N, M>)],
+    start: (Coord<N>, Coord<M>),
+) -> Option<usize> {
+    let max_moves = (N / 2 + M / 2) * TICKS_PER_BEAT as usize;
+    if !survivable(strikes, start, max_moves) {
+        return None;
+    }
+    // More moves never make an attack harder to survive, so the fewest
+    // moves that are enough can be bisected.
+    let (mut low, mut high) = (0, max_moves);
+    while low < high {
+        let moves = (low + high) / 2;
+        if survivable(strikes, start, moves) {
+            high = moves;
+        } else {
+            low = moves + 1;
+        }
+    }
+    Some(low)
+}
+
+fn relative_starts<const K: usize>(origin: &RingOrigin) -> Vec<Coord<K>> {
+    match origin {
+        RingOrigin::Player(offset) => vec![-Coord::new(offset.rem_euclid(K as isize) as usize)],
+        RingOrigin::Random | RingOrigin::Positions(_) => Coord::iter_all().collect(),
+    }
+}
+
+pub fn analyze_attack<const N: usize, const M: usize>(
+    attack: &Attack<N, M>,
+) -> AttackAnalysis<N, M> {
+    let mut analysis = AttackAnalysis {
+        required_moves: Some(0),
+        worst_start: (Coord::ZERO, Coord::ZERO),
+        worst_transform: Transform::default(),
+    };
+    // Patterns move along with the origin, so only the player's position
+    // relative to it matters. An origin that follows the player always puts
+    // the player at the same spot relative to it.
+    let origin = &attack.transform.origin;
+    let inner_starts = relative_starts::<N>(&origin.inner);
+    let outer_starts = relative_starts::<M>(&origin.outer);
+    for transform in attack.transform.variants() {
+        let strikes = strikes(attack, transform);
+        for &i in &inner_starts {
+            for &o in &outer_starts {
+                let start = (i, o);
+                let moves = required_moves(&strikes, start);
+                let worse = match (moves, analysis.required_moves) {
+                    (None, Some(_)) => true,
+                    (Some(moves), Some(worst)) => moves > worst,
+                    (_, None) => false,
+                };
+                if worse {
+                    analysis = AttackAnalysis {
+                        required_moves: moves,
+                        worst_start: start,
+                        worst_transform: transform,
+                    };
+                }
+            }
+        }
+    }
+    analysis
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::level::Level;
+
+    #[test]
+    fn bisection_finds_the_fewest_moves() {
+        let level: Level<6, 6> = include_str!("../levels/beta/level.json").parse().unwrap();
+        for attack in &level.attacks {
+            for transform in attack.transform.variants() {
+                let strikes = strikes(attack, transform);
+                for start in positions::<6, 6>() {
+                    let max_moves = 6 * TICKS_PER_BEAT as usize;
+                    let fewest = (0..=max_moves).find(|&moves| survivable(&strikes, start, moves));
+                    assert_eq!(required_moves(&strikes, start), fewest, "{}", attack.name);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn analysis_finds_impossible_and_easy_attacks() {
+        let attack = |patterns| -> Attack<6, 6> {
+            serde_json::from_value(json!({
+                "patterns": {"A": patterns},
+                "timeline": ["warn A", "strike A"],
+            }))
+            .unwrap()
+        };
+        assert!(analyze_attack(&attack("all")).is_impossible());
+        assert_eq!(analyze_attack(&attack("none")).required_moves, Some(0));
+        let ring = analyze_attack(&attack("ring-outer:0"));
+        assert_eq!(ring.required_moves, Some(1));
+        assert_eq!(ring.worst_start.1, Coord::ZERO);
+    }
+
+    #[test]
+    fn long_steps_are_walked_by_offset() {
+        let attack: Attack<6, 6> = serde_json::from_value(json!({
+            "patterns": {"A": "ring-outer:0", "B": "ring-outer:3"},
+            "timeline": ["warn A 64", "strike A", "rest 64", "strike B 0.5", "strike A 0.5"],
+        }))
+        .unwrap();
+        let strikes = strikes(&attack, Transform::default());
+        let ticks: Vec<_> = strikes.iter().map(|(tick, _)| *tick).collect();
+        assert_eq!(ticks, [128, 258, 259]);
+        // Only the players starting on outer position 0 have to move.
+        let analysis = analyze_attack(&attack);
+        assert_eq!(analysis.required_moves, Some(1));
+        assert_eq!(analysis.worst_start.1, Coord::ZERO);
+    }
+}
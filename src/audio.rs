@@ -1,7 +1,15 @@
-use std::{error::Error, time::Duration};
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
+use in_ring_out_ring::{
+    level::LevelAudio,
+    simulation::{TICKS_PER_BEAT, Tick},
+};
 use kira::{
-    AudioManager, DefaultBackend, Easing, StartTime, Tween,
+    AudioManager, Decibels, DefaultBackend, Easing, PlaybackRate, StartTime, Tween,
     clock::{ClockHandle, ClockSpeed},
     sound::{
         FromFileError,
@@ -15,7 +23,7 @@ mod files {
     pub const HIGH_DRUM: &str = "high_drum.mp3";
     pub const LOW_DRUM: &str = "low_drum.mp3";
     pub const DEATH: &str = "death.mp3";
-    pub const MUSIC: &str = "beta_level.wav";
+    pub const MUSIC: &str = "music.wav";
 }
 
 const INSTANT_TWEEN: Tween = Tween {
@@ -26,6 +34,38 @@ const INSTANT_TWEEN: Tween = Tween {
 
 type AudioResult<T> = Result<T, Box<dyn Error>>;
 
+#[derive(Clone, Debug)]
+pub struct AudioFiles {
+    pub music: PathBuf,
+    pub countdown_low: PathBuf,
+    pub countdown_high: PathBuf,
+    pub death: PathBuf,
+}
+
+impl AudioFiles {
+    pub fn for_level(level_dir: &Path, audio: &LevelAudio) -> Self {
+        let resolve = |path: &Option<PathBuf>, default: PathBuf| match path {
+            Some(path) => level_dir.join(path),
+            None => default,
+        };
+        Self {
+            music: resolve(&audio.music, level_dir.join(files::MUSIC)),
+            countdown_low: resolve(&audio.countdown_low, files::LOW_DRUM.into()),
+            countdown_high: resolve(&audio.countdown_high, files::HIGH_DRUM.into()),
+            death: resolve(&audio.death, files::DEATH.into()),
+        }
+    }
+
+    pub fn check(&self) -> AudioResult<()> {
+        let error = |path: &Path, e: FromFileError| format!("can't load {}: {e}", path.display());
+        StreamingSoundData::from_file(&self.music).map_err(|e| error(&self.music, e))?;
+        for path in [&self.countdown_low, &self.countdown_high, &self.death] {
+            StaticSoundData::from_file(path).map_err(|e| error(path, e))?;
+        }
+        Ok(())
+    }
+}
+
 pub struct Sounds {
     pub high_drum: StaticSoundData,
     pub low_drum: StaticSoundData,
@@ -33,11 +73,11 @@ pub struct Sounds {
 }
 
 impl Sounds {
-    pub fn new() -> AudioResult<Self> {
+    pub fn new(files: &AudioFiles) -> AudioResult<Self> {
         Ok(Self {
-            high_drum: StaticSoundData::from_file(files::HIGH_DRUM)?,
-            low_drum: StaticSoundData::from_file(files::LOW_DRUM)?,
-            death: StaticSoundData::from_file(files::DEATH)?,
+            high_drum: StaticSoundData::from_file(&files.countdown_high)?,
+            low_drum: StaticSoundData::from_file(&files.countdown_low)?,
+            death: StaticSoundData::from_file(&files.death)?,
         })
     }
 }
@@ -49,15 +89,14 @@ struct MusicProgress {
     limit: i32,
 }
 
-#[derive(Clone, Copy, Debug)]
-pub enum Tick {
-    Countdown(u64),
-    Beat(u64),
-}
-
 pub struct Speaker {
     manager: AudioManager,
     beats_per_second: f64,
+    tempo: f64,
+    music_rate: f64,
+    music_path: PathBuf,
+    music_seconds: f64,
+    music_start: f64,
     music: StreamingSoundHandle<FromFileError>,
     clock: ClockHandle,
     num_ticks_processed: u64,
@@ -65,10 +104,11 @@ pub struct Speaker {
 }
 
 impl Speaker {
-    pub fn new(bpm: f64) -> AudioResult<Self> {
+    pub fn new(bpm: f64, music_path: impl Into<PathBuf>) -> AudioResult<Self> {
+        let music_path = music_path.into();
         let mut manager = AudioManager::<DefaultBackend>::new(Default::default())?;
-        let clock = manager.add_clock(ClockSpeed::TicksPerMinute(bpm))?;
-        let music = StreamingSoundData::from_file(files::MUSIC)?;
+        let clock = manager.add_clock(ClockSpeed::TicksPerMinute(bpm * TICKS_PER_BEAT as f64))?;
+        let music = StreamingSoundData::from_file(&music_path)?;
         let seconds = music.duration().as_secs_f64();
         let beats_per_second = bpm / 60.0;
         let progress = MusicProgress {
@@ -81,6 +121,11 @@ impl Speaker {
         Ok(Speaker {
             manager,
             beats_per_second,
+            tempo: 1.0,
+            music_rate: 1.0,
+            music_path,
+            music_seconds: seconds,
+            music_start: 0.0,
             music,
             clock,
             num_ticks_processed: 0,
@@ -89,40 +134,78 @@ impl Speaker {
     }
 
     pub fn tick(&self, countdown_length: u64) -> Tick {
-        let t = self.clock.time().ticks;
-        if t < countdown_length {
-            Tick::Countdown(t)
-        } else {
-            Tick::Beat(t - countdown_length)
-        }
+        Tick::new(self.clock.time().ticks / TICKS_PER_BEAT, countdown_length)
     }
 
-    pub fn beat_fraction(&self) -> f64 {
+    pub fn tick_fraction(&self) -> f64 {
         self.clock.time().fraction
     }
 
+    pub fn time(&self) -> f64 {
+        let time = self.clock.time();
+        (time.ticks as f64 + time.fraction) / TICKS_PER_BEAT as f64
+    }
+
+    pub fn beats_per_second(&self) -> f64 {
+        self.beats_per_second * self.tempo
+    }
+
+    pub fn set_bpm(&mut self, bpm: f64) {
+        let tempo = bpm / 60.0 / self.beats_per_second;
+        if tempo == self.tempo {
+            return;
+        }
+        self.tempo = tempo;
+        self.clock.set_speed(
+            ClockSpeed::TicksPerMinute(bpm * TICKS_PER_BEAT as f64),
+            INSTANT_TWEEN,
+        );
+        self.music
+            .set_playback_rate(self.music_rate * tempo, INSTANT_TWEEN);
+    }
+
+    pub fn music_record(&self) -> i32 {
+        self.progress.record
+    }
+
+    pub fn set_music_record(&mut self, record: i32) {
+        self.progress.record = record.min(self.progress.limit - 1).max(0);
+    }
+
     pub fn update_music_progress(&mut self, beat: u64) {
-        if beat != 0 && beat % 16 == 0 {
+        if beat != 0 && beat.is_multiple_of(16) {
             let progress = &mut self.progress;
             progress.curr = (progress.curr + 1) % progress.limit;
             progress.record = progress.record.max(progress.curr);
         }
     }
 
-    pub fn process_tick(&mut self, countdown_length: u64) -> Option<Tick> {
+    pub fn process_tick(&mut self) -> bool {
         if self.num_ticks_processed > self.clock.time().ticks {
-            return None;
+            return false;
         }
         self.num_ticks_processed += 1;
-        Some(self.tick(countdown_length))
+        true
     }
 
     pub fn restart_clock(&mut self) {
+        self.set_bpm(self.beats_per_second * 60.0);
         self.num_ticks_processed = 0;
         self.clock.stop();
         self.clock.start();
     }
 
+    pub fn set_volume(&mut self, volume: f64) {
+        let decibels = if volume > 0.0 {
+            Decibels((20.0 * volume.log10() as f32).max(Decibels::SILENCE.0))
+        } else {
+            Decibels::SILENCE
+        };
+        self.manager
+            .main_track()
+            .set_volume(decibels, INSTANT_TWEEN);
+    }
+
     pub fn play_sound(&mut self, sound: &StaticSoundData) -> AudioResult<()> {
         self.manager.play(sound.clone())?;
         Ok(())
@@ -131,12 +214,20 @@ impl Speaker {
     pub fn play_music(&mut self, rng: &mut impl Rng) -> AudioResult<()> {
         let progress = &mut self.progress;
         progress.curr = rng.random_range(0..=progress.record);
-        let beats = (progress.curr * 16) as f64;
+        self.music_start = (progress.curr * 16) as f64;
+        self.play_music_from(0.0, 1.0)
+    }
+
+    pub fn play_music_from(&mut self, beat: f64, rate: f64) -> AudioResult<()> {
         let seconds_per_beat = 1.0 / self.beats_per_second;
-        let music = StreamingSoundData::from_file(files::MUSIC)?
+        let position = ((self.music_start + beat) * seconds_per_beat) % self.music_seconds;
+        let music = StreamingSoundData::from_file(&self.music_path)?
             .loop_region(..)
-            .start_position(beats * seconds_per_beat);
+            .start_position(position)
+            .playback_rate(PlaybackRate(rate * self.tempo));
+        self.music.stop(INSTANT_TWEEN);
         self.music = self.manager.play(music)?;
+        self.music_rate = rate;
         Ok(())
     }
 
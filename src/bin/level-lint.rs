@@ -0,0 +1,51 @@
+use std::{fs, path::PathBuf, process::ExitCode};
+
+use clap::Parser;
+use in_ring_out_ring::lint::{self, Severity};
+
+/// Check level files for errors and likely mistakes
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// Level folders or level.json files to check
+    #[arg(required = true)]
+    levels: Vec<PathBuf>,
+    /// Exit with a failure status when there are warnings, not just errors
+    #[arg(long)]
+    deny_warnings: bool,
+    /// Also print notes, such as how many moves per beat each attack needs
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let mut worst = None;
+    for path in &args.levels {
+        let path = if path.is_dir() {
+            path.join("level.json")
+        } else {
+            path.clone()
+        };
+        let findings = match fs::read_to_string(&path) {
+            Ok(source) => lint::lint(&source, &path),
+            Err(e) => {
+                eprintln!("{}: error: can't read file: {e}", path.display());
+                worst = Some(Severity::Error);
+                continue;
+            }
+        };
+        for finding in &findings {
+            if finding.severity == Severity::Note && !args.verbose {
+                continue;
+            }
+            println!("{}: {finding}", path.display());
+        }
+        worst = worst.max(findings.iter().map(|finding| finding.severity).max());
+    }
+    if worst.is_some_and(|worst| worst.fails(args.deny_warnings)) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
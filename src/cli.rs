@@ -0,0 +1,79 @@
+use std::{path::PathBuf, str::FromStr};
+
+use clap::Parser;
+
+#[derive(Clone, Copy, Debug)]
+pub struct WindowSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for WindowSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("expected a size like 512x512, got {s:?}");
+        let (width, height) = s.split_once('x').ok_or_else(error)?;
+        let size = Self {
+            width: width.parse().map_err(|_| error())?,
+            height: height.parse().map_err(|_| error())?,
+        };
+        if size.width == 0 || size.height == 0 {
+            return Err(error());
+        }
+        Ok(size)
+    }
+}
+
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Args {
+    /// Start playing this level (a level folder or a level file) instead of showing level selection
+    #[arg(long, value_name = "PATH")]
+    pub level: Option<PathBuf>,
+    /// Seed every run with this value instead of a random one
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Initial window size in pixels
+    #[arg(long, value_name = "WIDTHxHEIGHT", default_value = "512x512")]
+    pub window_size: WindowSize,
+    /// Start in fullscreen mode
+    #[arg(long)]
+    pub fullscreen: bool,
+    /// Turn off all sound
+    #[arg(long)]
+    pub mute: bool,
+    /// Sound volume in percent
+    #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub volume: u8,
+    /// Watch a recorded replay; its level is looked up in the levels folder unless --level is given
+    #[arg(long, value_name = "FILE")]
+    pub replay: Option<PathBuf>,
+    /// Practice the attack with this name over and over instead of playing the level
+    #[arg(
+        long,
+        value_name = "ATTACK",
+        requires = "level",
+        conflicts_with = "replay"
+    )]
+    pub practice: Option<String>,
+    /// Speed of practice mode in percent of the level's BPM
+    #[arg(long, value_name = "PERCENT", default_value_t = 100, value_parser = clap::value_parser!(u8).range(10..=100))]
+    pub practice_speed: u8,
+    /// Open the level given with --level in the level editor
+    #[arg(long, requires = "level", conflicts_with_all = ["replay", "practice"])]
+    pub edit: bool,
+    /// Check that a level (a level folder or a level file) loads, then exit
+    #[arg(long, value_name = "PATH", exclusive = true)]
+    pub validate: Option<PathBuf>,
+}
+
+impl Args {
+    pub fn volume(&self) -> f64 {
+        if self.mute {
+            0.0
+        } else {
+            f64::from(self.volume) / 100.0
+        }
+    }
+}
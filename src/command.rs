@@ -2,8 +2,10 @@ use std::collections::VecDeque;
 
 use rand::Rng;
 
-use crate::level::{Attack, AttackPatterns, Origin, TransformBlueprint};
+use crate::level::{Attack, RingOrigin, Schedule, Step, StepAction, Timeline, TransformBlueprint};
 use crate::playfield::{Coord, Pattern, Transform};
+use crate::script::StyleChange;
+use crate::simulation::TICKS_PER_BEAT;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Flash {
@@ -12,90 +14,134 @@ pub enum Flash {
 }
 
 #[derive(Debug)]
-pub enum Command<const N: usize> {
-    NewAttack(Attack<N>, Transform<N>),
-    FlashPattern(Pattern<N>, Flash),
+pub enum Command<const N: usize, const M: usize> {
+    NewAttack(Attack<N, M>, Transform<N, M>),
+    FlashPattern(Pattern<N, M>, Flash, u64),
+    ChangeStyle(StyleChange<N, M>),
 }
 
-impl<const N: usize> Command<N> {
-    fn warn(pattern: Pattern<N>) -> Self {
-        Command::FlashPattern(pattern, Flash::Warn)
-    }
-
-    fn strike(pattern: Pattern<N>) -> Self {
-        Command::FlashPattern(pattern, Flash::Strike)
+impl RingOrigin {
+    fn construct<const K: usize>(&self, rng: &mut impl Rng, player: Coord<K>) -> Coord<K> {
+        match self {
+            RingOrigin::Random => rng.random(),
+            RingOrigin::Player(offset) => {
+                player + Coord::new(offset.rem_euclid(K as isize) as usize)
+            }
+            RingOrigin::Positions(positions) => {
+                Coord::new(positions[rng.random_range(0..positions.len())])
+            }
+        }
     }
 }
 
 impl TransformBlueprint {
-    pub fn construct<const N: usize>(
+    pub fn construct<const N: usize, const M: usize>(
         &self,
         rng: &mut impl Rng,
-        player: (Coord<N>, Coord<N>),
-    ) -> Transform<N> {
+        player: (Coord<N>, Coord<M>),
+    ) -> Transform<N, M> {
+        let mirror = self.mirror && rng.random_bool(0.5);
         Transform {
-            origin: match self.origin {
-                Origin::Random => rng.random(),
-                Origin::Targeted => player,
-            },
+            origin: (
+                self.origin.inner.construct(rng, player.0),
+                self.origin.outer.construct(rng, player.1),
+            ),
             transpose: self.transpose && rng.random_bool(0.5),
-            mirror: self.mirror && rng.random_bool(0.5),
+            mirror_inner: mirror ^ (self.mirror_inner && rng.random_bool(0.5)),
+            mirror_outer: mirror ^ (self.mirror_outer && rng.random_bool(0.5)),
+            reverse: self.reverse && rng.random_bool(0.5),
         }
     }
+
+    pub fn variants<const N: usize, const M: usize>(&self) -> Vec<Transform<N, M>> {
+        let flags = |allowed| {
+            if allowed {
+                vec![false, true]
+            } else {
+                vec![false]
+            }
+        };
+        let mut variants = Vec::new();
+        for transpose in flags(self.transpose) {
+            for mirror in flags(self.mirror) {
+                for mirror_inner in flags(self.mirror_inner) {
+                    for mirror_outer in flags(self.mirror_outer) {
+                        for reverse in flags(self.reverse) {
+                            let transform = Transform {
+                                origin: (Coord::ZERO, Coord::ZERO),
+                                transpose,
+                                mirror_inner: mirror ^ mirror_inner,
+                                mirror_outer: mirror ^ mirror_outer,
+                                reverse,
+                            };
+                            if !variants.contains(&transform) {
+                                variants.push(transform);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        variants
+    }
 }
 
-impl<const N: usize> Attack<N> {
-    pub fn beat_length(&self) -> u64 {
-        match self.patterns {
-            AttackPatterns::Four(_) => 4,
-            AttackPatterns::FourPlusFour(_) => 8,
-            AttackPatterns::Eight(_) => 8,
-            AttackPatterns::EightPlusEight(_) => 16,
+impl<const N: usize, const M: usize> Attack<N, M> {
+    pub fn label(&self, index: usize) -> String {
+        if self.name.is_empty() {
+            format!("attack {index}")
+        } else {
+            format!("attack \"{}\"", self.name)
         }
     }
 
-    pub fn weight(&self) -> f32 {
-        match self.patterns {
-            AttackPatterns::Four(_) => 0.25,
-            AttackPatterns::FourPlusFour(_) => 0.5,
-            AttackPatterns::Eight(_) => 0.5,
-            AttackPatterns::EightPlusEight(_) => 1.0,
+    pub fn ticks(&self) -> u64 {
+        self.patterns
+            .timeline()
+            .steps
+            .iter()
+            .map(|step| step.ticks)
+            .sum()
+    }
+
+    pub fn beat_length(&self) -> u64 {
+        self.ticks().div_ceil(TICKS_PER_BEAT)
+    }
+
+    pub fn weight(&self, beat: u64) -> f32 {
+        let Schedule {
+            weight,
+            min_beat,
+            max_beat,
+        } = &self.schedule;
+        if min_beat.is_some_and(|min_beat| beat < min_beat)
+            || max_beat.is_some_and(|max_beat| beat > max_beat)
+        {
+            return 0.0;
+        }
+        match weight {
+            Some(weight) => weight.at(beat),
+            None => self.ticks() as f32 / (16 * TICKS_PER_BEAT) as f32,
         }
     }
 
-    pub fn enqueue(self, commands: &mut VecDeque<Command<N>>, transform: Transform<N>) {
+    pub fn enqueue(self, commands: &mut VecDeque<Command<N, M>>, transform: Transform<N, M>) {
         commands.push_back(Command::NewAttack(self.clone(), transform));
-        match self.patterns {
-            AttackPatterns::Four([pattern]) => {
-                let pattern = pattern.construct(transform);
-                for _ in 0..3 {
-                    commands.push_back(Command::warn(pattern.clone()));
-                }
-                commands.push_back(Command::strike(pattern));
-            }
-            AttackPatterns::FourPlusFour(patterns) => {
-                let patterns = patterns.map(|p| p.construct(transform));
-                commands.extend(patterns.clone().map(Command::warn));
-                commands.push_back(Command::warn(Pattern::empty()));
-                commands.extend(patterns.map(Command::strike));
-                commands.push_back(Command::strike(Pattern::empty()));
-            }
-            AttackPatterns::Eight([pattern]) => {
-                let pattern = pattern.construct(transform);
-                for _ in 0..7 {
-                    commands.push_back(Command::warn(pattern.clone()));
-                }
-                commands.push_back(Command::strike(pattern));
-            }
-            AttackPatterns::EightPlusEight(patterns) => {
-                let patterns = patterns.map(|p| p.construct(transform));
-                commands.extend(patterns.clone().map(Command::warn));
-                commands.push_back(Command::warn(Pattern::empty()));
-                commands.push_back(Command::warn(Pattern::empty()));
-                commands.extend(patterns.map(Command::strike));
-                commands.push_back(Command::strike(Pattern::empty()));
-                commands.push_back(Command::strike(Pattern::empty()));
-            }
+        let Timeline { patterns, steps } = self.patterns.timeline();
+        let mut patterns: Vec<_> = patterns
+            .iter()
+            .map(|(_, pattern)| pattern.construct(transform))
+            .collect();
+        if transform.reverse {
+            patterns.reverse();
+        }
+        for Step { action, ticks } in steps {
+            let (pattern, flash) = match action {
+                StepAction::Warn(p) => (patterns[p].clone(), Flash::Warn),
+                StepAction::Strike(p) => (patterns[p].clone(), Flash::Strike),
+                StepAction::Rest => (Pattern::empty(), Flash::Warn),
+            };
+            commands.push_back(Command::FlashPattern(pattern, flash, ticks));
         }
     }
 }
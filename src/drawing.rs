@@ -1,37 +1,165 @@
 use macroquad::{
-    color::{Color, WHITE},
+    color::{BLACK, Color, WHITE},
     shapes::{draw_poly, draw_poly_lines, draw_rectangle},
-    text::{draw_text, get_text_center},
+    text::{draw_text, get_text_center, measure_text},
 };
 
-use crate::command::Flash;
-use crate::level::LevelColors;
-use crate::playfield::{Coord, Pattern};
+use in_ring_out_ring::{
+    command::Flash,
+    level::LevelColors,
+    playfield::{Coord, Pattern},
+};
 
 #[derive(Debug)]
-pub struct Screen<const N: usize> {
+pub struct Screen<const N: usize, const M: usize> {
     playfield_size: f32,
 }
 
+#[derive(Clone, Copy, Debug)]
+pub enum Flashes<'a, const N: usize, const M: usize> {
+    Blinking {
+        pattern: &'a Pattern<N, M>,
+        flash: Flash,
+        draw_flashes: bool,
+    },
+    Limited(&'a FlashLimiter<N, M>),
+}
+
+#[derive(Debug)]
+pub struct FlashLimiter<const N: usize, const M: usize> {
+    warn: [[f32; N]; M],
+    strike: [[f32; N]; M],
+    fade: f32,
+    fading_in: bool,
+}
+
 #[derive(Clone, Copy, Debug)]
 enum DrawMode {
     Fill,
     Stroke(f32),
 }
 
-impl<const N: usize> Screen<N> {
+const MAX_LUMINANCE_STEP: f32 = 0.05;
+const FADE_OPACITY: f32 = 0.6;
+
+fn luminance(color: Color) -> f32 {
+    0.2126 * color.r + 0.7152 * color.g + 0.0722 * color.b
+}
+
+fn mix(from: Color, to: Color, t: f32) -> Color {
+    Color::new(
+        from.r + (to.r - from.r) * t,
+        from.g + (to.g - from.g) * t,
+        from.b + (to.b - from.b) * t,
+        1.0,
+    )
+}
+
+fn approach(value: &mut f32, target: f32, contrast: f32) {
+    let step = MAX_LUMINANCE_STEP / contrast.max(MAX_LUMINANCE_STEP);
+    *value += (target - *value).clamp(-step, step);
+}
+
+impl<const N: usize, const M: usize> FlashLimiter<N, M> {
+    pub fn new() -> Self {
+        Self {
+            warn: [[0.0; N]; M],
+            strike: [[0.0; N]; M],
+            fade: 0.0,
+            fading_in: false,
+        }
+    }
+
+    pub fn start_fade(&mut self) {
+        self.fading_in = true;
+    }
+
+    pub fn update(
+        &mut self,
+        pattern: &Pattern<N, M>,
+        flash: Flash,
+        draw_flashes: bool,
+        colors: &LevelColors<N, M>,
+    ) {
+        let warn_contrast = (luminance(colors.flash) - luminance(colors.background)).abs();
+        for o in Coord::iter_all() {
+            for i in Coord::iter_all() {
+                let lit = draw_flashes && pattern[(i, o)];
+                let strike_contrast =
+                    (luminance(colors.flash) - luminance(colors.main[i.inner()])).abs();
+                let (warn, strike) = match (lit, flash) {
+                    (false, _) => (0.0, 0.0),
+                    (true, Flash::Warn) => (1.0, 0.0),
+                    (true, Flash::Strike) => (0.0, 1.0),
+                };
+                approach(&mut self.warn[o.inner()][i.inner()], warn, warn_contrast);
+                approach(
+                    &mut self.strike[o.inner()][i.inner()],
+                    strike,
+                    strike_contrast,
+                );
+            }
+        }
+        let target = if self.fading_in { 1.0 } else { 0.0 };
+        approach(&mut self.fade, target, FADE_OPACITY);
+        if self.fade >= 1.0 {
+            self.fading_in = false;
+        }
+    }
+}
+
+fn orbit<const N: usize>(
+    n: Coord<N>,
+    (x, y): (f32, f32),
+    radius: f32,
+    rotation: f32,
+) -> (f32, f32) {
+    let t = ((90.0 - rotation) - 360.0 * (n.inner() as f32) / (N as f32)).to_radians();
+    (x + t.cos() * radius, y + t.sin() * radius)
+}
+
+impl<const N: usize, const M: usize> Screen<N, M> {
     pub fn new(width: f32, height: f32) -> Self {
         Self {
             playfield_size: width.min(height),
         }
     }
 
+    // The outer ring's radius, the inner rings' radius and the tiles' radius.
+    fn radii() -> (f32, f32, f32) {
+        let r = |sides: usize| 1.0 / (180.0 / (sides as f32)).to_radians().sin();
+        let (in_r, out_r) = (r(N), r(M));
+        let base_radius = 1.0 / ((1.0 + in_r) * (1.0 + out_r));
+        let medium_radius = base_radius * in_r;
+        let large_radius = (base_radius + medium_radius) * out_r;
+        let small_radius = base_radius * in_r / (1.0 + in_r);
+        (large_radius, medium_radius, small_radius)
+    }
+
+    pub fn tile_at(
+        &self,
+        (x, y): (f32, f32),
+        (in_rotation, out_rotation): (f32, f32),
+    ) -> Option<(Coord<N>, Coord<M>)> {
+        let scale = self.playfield_size / 2.0;
+        let (x, y) = (x / scale - 1.0, 1.0 - y / scale);
+        let (large_radius, medium_radius, small_radius) = Self::radii();
+        Coord::iter_all()
+            .flat_map(|o| Coord::iter_all().map(move |i| (i, o)))
+            .find(|&(i, o)| {
+                let center = orbit(o, (0.0, 0.0), large_radius, out_rotation);
+                let (tile_x, tile_y) = orbit(i, center, medium_radius, in_rotation);
+                (x - tile_x).hypot(y - tile_y) <= small_radius
+            })
+    }
+
     fn fill_background(&self, color: Color) {
         draw_rectangle(0.0, 0.0, self.playfield_size, self.playfield_size, color);
     }
 
     fn draw_polygon(
         &self,
+        sides: usize,
         (x, y): (f32, f32),
         radius: f32,
         draw_mode: DrawMode,
@@ -45,7 +173,7 @@ impl<const N: usize> Screen<N> {
         let scale = self.playfield_size / 2.0;
         let x = (x + 1.0) * scale;
         let y = (-y + 1.0) * scale;
-        let sides = N as u8;
+        let sides = sides as u8;
         let radius = (radius - thickness.unwrap_or(0.0) / 2.0) * scale;
         let rotation = rotation - 90.0;
         if let Some(thickness) = thickness {
@@ -58,32 +186,17 @@ impl<const N: usize> Screen<N> {
 
     pub fn draw_playfield(
         &self,
-        pattern: &Pattern<N>,
-        flash: Flash,
-        draw_flashes: bool,
-        player: (Coord<N>, Coord<N>),
+        flashes: Flashes<N, M>,
+        player: (Coord<N>, Coord<M>),
         rotation: (f32, f32),
-        colors: &LevelColors<N>,
+        colors: &LevelColors<N, M>,
     ) {
-        fn orbit<const N: usize>(
-            n: Coord<N>,
-            (x, y): (f32, f32),
-            radius: f32,
-            rotation: f32,
-        ) -> (f32, f32) {
-            let t = ((90.0 - rotation) - 360.0 * (n.inner() as f32) / (N as f32)).to_radians();
-            (x + t.cos() * radius, y + t.sin() * radius)
-        }
-
         let (in_rotation, out_rotation) = rotation;
         self.fill_background(colors.background);
-        let r = 1.0 / (180.0 / (N as f32)).to_radians().sin();
-        let base_radius = 1.0 / (1.0 + r + r + r * r);
-        let medium_radius = base_radius * r;
-        let large_radius = (base_radius + medium_radius) * r;
-        let small_radius = medium_radius * (medium_radius / large_radius);
+        let (large_radius, medium_radius, small_radius) = Self::radii();
         let line_thickness = small_radius / 3.0;
         self.draw_polygon(
+            M,
             (0.0, 0.0),
             large_radius,
             DrawMode::Stroke(line_thickness),
@@ -93,17 +206,19 @@ impl<const N: usize> Screen<N> {
         for o in Coord::iter_all() {
             let (x, y) = orbit(o, (0.0, 0.0), large_radius, out_rotation);
             self.draw_polygon(
+                N,
                 (x, y),
                 medium_radius,
                 DrawMode::Stroke(line_thickness),
                 in_rotation,
-                colors.main[o.inner()],
+                colors.ring(o),
             );
             for i in Coord::iter_all() {
                 let (x, y) = orbit(i, (x, y), medium_radius, in_rotation);
                 let regular_color = colors.main[i.inner()];
                 let draw_tile = |scale, color| {
                     self.draw_polygon(
+                        N,
                         (x, y),
                         small_radius * scale,
                         DrawMode::Fill,
@@ -111,13 +226,37 @@ impl<const N: usize> Screen<N> {
                         color,
                     );
                 };
-                match (draw_flashes && pattern[(i, o)], flash) {
-                    (false, _) => draw_tile(1.0, regular_color),
-                    (true, Flash::Warn) => {
-                        draw_tile(4.0 / 3.0, colors.flash);
-                        draw_tile(1.0, regular_color);
+                match flashes {
+                    Flashes::Blinking {
+                        pattern,
+                        flash,
+                        draw_flashes,
+                    } => match (draw_flashes && pattern[(i, o)], flash) {
+                        (false, _) => draw_tile(1.0, regular_color),
+                        (true, Flash::Warn) => {
+                            draw_tile(4.0 / 3.0, colors.flash);
+                            draw_tile(1.0, regular_color);
+                        }
+                        (true, Flash::Strike) => draw_tile(4.5 / 3.0, colors.flash),
+                    },
+                    Flashes::Limited(limiter) => {
+                        let warn = limiter.warn[o.inner()][i.inner()];
+                        let strike = limiter.strike[o.inner()][i.inner()];
+                        if warn > 0.0 {
+                            self.draw_polygon(
+                                N,
+                                (x, y),
+                                small_radius * 4.0 / 3.0,
+                                DrawMode::Stroke(small_radius / 3.0),
+                                in_rotation,
+                                mix(colors.background, colors.flash, warn),
+                            );
+                        }
+                        draw_tile(
+                            1.0 + strike * 1.5 / 3.0,
+                            mix(regular_color, colors.flash, strike),
+                        );
                     }
-                    (true, Flash::Strike) => draw_tile(4.5 / 3.0, colors.flash),
                 }
                 if (i, o) == player {
                     draw_tile(2.0 / 3.0, colors.player);
@@ -126,15 +265,56 @@ impl<const N: usize> Screen<N> {
         }
     }
 
-    pub fn draw_text(&self, text: &str, y: f32) {
+    fn draw_text_sized(&self, text: &str, y: f32, size: f32) {
         let x = self.playfield_size / 2.0;
         let y = (-y + 1.0) * self.playfield_size / 2.0;
-        let font_size = self.playfield_size / 8.0;
+        let font_size = self.playfield_size * size;
         let center = get_text_center(text, None, font_size as u16, 1.0, 0.0);
         draw_text(text, x - center.x, y - center.y, font_size, WHITE);
     }
 
+    pub fn draw_text(&self, text: &str, y: f32) {
+        self.draw_text_sized(text, y, 1.0 / 8.0);
+    }
+
+    pub fn draw_caption(&self, text: &str, y: f32) {
+        self.draw_text_sized(text, y, 1.0 / 24.0);
+    }
+
+    // Draws a caption wrapped to the width of the playfield, one line below
+    // the other starting at `y`.
+    pub fn draw_paragraph(&self, text: &str, y: f32) {
+        let font_size = (self.playfield_size / 24.0) as u16;
+        let max_width = self.playfield_size * 15.0 / 16.0;
+        let mut lines: Vec<String> = Vec::new();
+        for word in text.split_whitespace() {
+            match lines.last_mut() {
+                Some(line)
+                    if measure_text(&format!("{line} {word}"), None, font_size, 1.0).width
+                        <= max_width =>
+                {
+                    line.push(' ');
+                    line.push_str(word);
+                }
+                _ => lines.push(word.to_owned()),
+            }
+        }
+        for (k, line) in lines.iter().enumerate() {
+            self.draw_caption(line, y - k as f32 / 16.0);
+        }
+    }
+
     pub fn flash(&self) {
         self.fill_background(WHITE);
     }
+
+    pub fn fade(&self, limiter: &FlashLimiter<N, M>) {
+        let Color { r, g, b, .. } = BLACK;
+        let alpha = limiter.fade * FADE_OPACITY;
+        self.fill_background(Color::new(r, g, b, alpha));
+    }
+
+    pub fn blank(&self) {
+        self.fill_background(BLACK);
+    }
 }
@@ -0,0 +1,408 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use in_ring_out_ring::{
+    command::Flash,
+    level::{self, Attack, AttackPatterns, Level, LevelColors, Origin, RingOrigin},
+    playfield::{Coord, Pattern, PatternBlueprint, Transform},
+    simulation::AttackLoop,
+};
+use macroquad::input::{
+    KeyCode, MouseButton, is_key_pressed, is_mouse_button_pressed, mouse_position,
+};
+use rand::Rng;
+use serde_json::{Map, Value, json};
+
+use crate::{
+    audio::Speaker,
+    drawing::{Flashes, Screen},
+};
+
+const BEATS: [&str; 4] = ["4", "4+4", "8", "8+8"];
+
+pub struct Editor<const N: usize, const M: usize> {
+    path: PathBuf,
+    document: Value,
+    attacks: Vec<Attack<N, M>>,
+    loaded: Vec<Attack<N, M>>,
+    attack: usize,
+    pattern: usize,
+    preview: Option<Box<AttackLoop<N, M>>>,
+    bpm: f64,
+    // The speaker's bpm from before the preview, to go back to afterwards.
+    previous_bpm: Option<f64>,
+    draw_flashes: bool,
+    saved: bool,
+    status: String,
+}
+
+pub enum EditorResult {
+    Open,
+    Closed,
+}
+
+fn beats<const N: usize, const M: usize>(patterns: &AttackPatterns<N, M>) -> Option<usize> {
+    match patterns {
+        AttackPatterns::Four(_) => Some(0),
+        AttackPatterns::FourPlusFour(_) => Some(1),
+        AttackPatterns::Eight(_) => Some(2),
+        AttackPatterns::EightPlusEight(_) => Some(3),
+        AttackPatterns::Timeline(_) => None,
+    }
+}
+
+fn with_beats<const N: usize, const M: usize>(
+    beats: usize,
+    blueprints: &[PatternBlueprint<N, M>],
+) -> AttackPatterns<N, M> {
+    fn take<const N: usize, const M: usize, const P: usize>(
+        blueprints: &[PatternBlueprint<N, M>],
+    ) -> [PatternBlueprint<N, M>; P] {
+        std::array::from_fn(|k| {
+            blueprints
+                .get(k)
+                .cloned()
+                .unwrap_or_else(PatternBlueprint::empty)
+        })
+    }
+
+    match beats {
+        0 => AttackPatterns::Four(take(blueprints)),
+        1 => AttackPatterns::FourPlusFour(take(blueprints)),
+        2 => AttackPatterns::Eight(take(blueprints)),
+        _ => AttackPatterns::EightPlusEight(take(blueprints)),
+    }
+}
+
+fn blueprints_mut<const N: usize, const M: usize>(
+    patterns: &mut AttackPatterns<N, M>,
+) -> Vec<&mut PatternBlueprint<N, M>> {
+    match patterns {
+        AttackPatterns::Four(patterns) | AttackPatterns::Eight(patterns) => {
+            patterns.iter_mut().collect()
+        }
+        AttackPatterns::FourPlusFour(patterns) => patterns.iter_mut().collect(),
+        AttackPatterns::EightPlusEight(patterns) => patterns.iter_mut().collect(),
+        AttackPatterns::Timeline(timeline) => timeline
+            .patterns
+            .iter_mut()
+            .map(|(_, pattern)| pattern)
+            .collect(),
+    }
+}
+
+fn targeted() -> Origin {
+    Origin {
+        inner: RingOrigin::Player(0),
+        outer: RingOrigin::Player(0),
+    }
+}
+
+// Only the fields the editor can change are replaced, so everything else
+// in the attack is written back as it was.
+fn attack_value<const N: usize, const M: usize>(
+    attack: &Attack<N, M>,
+    object: &mut Map<String, Value>,
+) {
+    let Ok(Value::Object(serialized)) = serde_json::to_value(attack) else {
+        return;
+    };
+    for key in ["beats", "patterns", "origin", "transpose", "mirror"] {
+        match serialized.get(key) {
+            Some(value) => {
+                object.insert(key.to_owned(), value.clone());
+            }
+            None => {
+                object.shift_remove(key);
+            }
+        }
+    }
+}
+
+impl<const N: usize, const M: usize> Editor<N, M> {
+    pub fn open(path: &Path, level: &Level<N, M>) -> Result<Self, String> {
+        let document: Value = fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|source| serde_json::from_str(&source).map_err(|e| e.to_string()))
+            .map_err(|e| format!("can't read {}: {e}", path.display()))?;
+        // Attacks included from libraries are edited in their own files.
+        let attacks = level.own_attacks().to_vec();
+        Ok(Self {
+            path: path.to_path_buf(),
+            document,
+            attacks: attacks.clone(),
+            loaded: attacks,
+            attack: 0,
+            pattern: 0,
+            preview: None,
+            bpm: level.bpm,
+            previous_bpm: None,
+            draw_flashes: true,
+            saved: true,
+            status: String::new(),
+        })
+    }
+
+    fn pattern_count(&self) -> usize {
+        self.attacks
+            .get(self.attack)
+            .map_or(0, |attack| attack.patterns.timeline().patterns.len())
+    }
+
+    fn add_attack(&mut self) {
+        let names = (self.attacks.len()..).map(|k| format!("attack-{k}"));
+        let name = names
+            .into_iter()
+            .find(|name| self.attacks.iter().all(|attack| attack.name != *name))
+            .unwrap_or_default();
+        if self.document["attacks"].is_null() {
+            self.document["attacks"] = json!([]);
+        }
+        if let Some(attacks) = self.document["attacks"].as_array_mut() {
+            attacks.push(json!({ "name": name }));
+        }
+        self.attacks.push(Attack {
+            name,
+            patterns: AttackPatterns::Four([PatternBlueprint::empty()]),
+            transform: Default::default(),
+            schedule: Default::default(),
+        });
+        self.attack = self.attacks.len() - 1;
+        self.pattern = 0;
+    }
+
+    fn save(&mut self) {
+        let Some(attacks) = self.document["attacks"].as_array_mut() else {
+            self.status = "the level has no attacks list".to_owned();
+            return;
+        };
+        // Attacks that weren't changed keep the notation they were written in.
+        for (k, (attack, value)) in self.attacks.iter().zip(attacks).enumerate() {
+            let changed = self.loaded.get(k).is_none_or(|loaded| {
+                loaded.patterns != attack.patterns || loaded.transform != attack.transform
+            });
+            if changed && let Some(object) = value.as_object_mut() {
+                attack_value(attack, object);
+            }
+        }
+        let source = level::to_pretty_json(&self.document);
+        if let Err(e) = fs::write(&self.path, &source) {
+            self.status = format!("can't save: {e}");
+            return;
+        }
+        self.saved = true;
+        self.loaded = self.attacks.clone();
+        self.status = match Level::<N, M>::load(&source, &self.path) {
+            Ok(_) => "saved".to_owned(),
+            Err(e) => format!("saved, but the level doesn't load: {e}"),
+        };
+    }
+
+    fn edit(&mut self, screen: &Screen<N, M>, rotation: (f32, f32)) {
+        if is_key_pressed(KeyCode::Insert) {
+            self.add_attack();
+        }
+        let count = self.attacks.len();
+        if count == 0 {
+            return;
+        }
+        if is_key_pressed(KeyCode::Up) {
+            self.attack = (self.attack + count - 1) % count;
+            self.pattern = 0;
+        }
+        if is_key_pressed(KeyCode::Down) {
+            self.attack = (self.attack + 1) % count;
+            self.pattern = 0;
+        }
+        let patterns = self.pattern_count();
+        let attack = &mut self.attacks[self.attack];
+        let before = (attack.patterns.clone(), attack.transform.clone());
+        // A timeline may only rest, in which case it has no patterns to pick
+        // or edit.
+        if patterns > 0 {
+            if is_key_pressed(KeyCode::Left) {
+                self.pattern = (self.pattern + patterns - 1) % patterns;
+            }
+            if is_key_pressed(KeyCode::Right) {
+                self.pattern = (self.pattern + 1) % patterns;
+            }
+            if is_mouse_button_pressed(MouseButton::Left)
+                && let Some(tile) = screen.tile_at(mouse_position(), rotation)
+            {
+                let pattern = &mut blueprints_mut(&mut attack.patterns)[self.pattern];
+                pattern[tile] = !pattern[tile];
+            }
+            if is_key_pressed(KeyCode::C) {
+                *blueprints_mut(&mut attack.patterns)[self.pattern] = PatternBlueprint::empty();
+            }
+        }
+        if is_key_pressed(KeyCode::B) {
+            match beats(&attack.patterns) {
+                Some(beats) => {
+                    let blueprints: Vec<_> = blueprints_mut(&mut attack.patterns)
+                        .into_iter()
+                        .map(|pattern| pattern.clone())
+                        .collect();
+                    attack.patterns = with_beats((beats + 1) % BEATS.len(), &blueprints);
+                    self.pattern = self
+                        .pattern
+                        .min(attack.patterns.timeline().patterns.len() - 1);
+                }
+                None => self.status = "timeline attacks don't have beats".to_owned(),
+            }
+        }
+        let transform = &mut attack.transform;
+        if is_key_pressed(KeyCode::O) {
+            transform.origin = if transform.origin == Origin::default() {
+                targeted()
+            } else {
+                Origin::default()
+            };
+        }
+        if is_key_pressed(KeyCode::T) {
+            if N == M {
+                transform.transpose = !transform.transpose;
+            } else {
+                self.status = "attacks can't be transposed when the rings differ".to_owned();
+            }
+        }
+        if is_key_pressed(KeyCode::M) {
+            transform.mirror = !transform.mirror;
+        }
+        if (attack.patterns.clone(), attack.transform.clone()) != before {
+            self.saved = false;
+            self.status.clear();
+        }
+    }
+
+    fn stop_preview(&mut self, speaker: &mut Speaker) {
+        self.preview = None;
+        if let Some(bpm) = self.previous_bpm.take() {
+            speaker.set_bpm(bpm);
+        }
+    }
+
+    pub fn update(
+        &mut self,
+        screen: &Screen<N, M>,
+        rotation: (f32, f32),
+        speaker: &mut Speaker,
+        reduced: bool,
+        rng: &mut impl Rng,
+    ) -> EditorResult {
+        if is_key_pressed(KeyCode::Escape) {
+            self.stop_preview(speaker);
+            return EditorResult::Closed;
+        }
+        if is_key_pressed(KeyCode::Space) {
+            match (&self.preview, self.attacks.get(self.attack)) {
+                (None, Some(attack)) => {
+                    let preview = AttackLoop::practice(attack.clone(), rng.random());
+                    self.previous_bpm = Some(speaker.beats_per_second() * 60.0);
+                    speaker.set_bpm(self.bpm);
+                    speaker.restart_clock();
+                    self.preview = Some(Box::new(preview));
+                }
+                _ => self.stop_preview(speaker),
+            }
+        }
+        match &mut self.preview {
+            Some(practice) => {
+                while speaker.process_tick() {
+                    practice.advance();
+                }
+                self.draw_flashes =
+                    reduced || practice.flash_progress(speaker.tick_fraction()) < 0.5;
+            }
+            None => {
+                self.edit(screen, rotation);
+                self.draw_flashes = true;
+            }
+        }
+        if is_key_pressed(KeyCode::S) {
+            self.save();
+        }
+        EditorResult::Open
+    }
+
+    pub fn draw(&self, screen: &Screen<N, M>, rotation: (f32, f32), colors: &LevelColors<N, M>) {
+        let Some(attack) = self.attacks.get(self.attack) else {
+            screen.blank();
+            screen.draw_text("no attacks", 1.0 / 8.0);
+            screen.draw_caption("Insert: add an attack - Escape: back", -3.0 / 4.0);
+            return;
+        };
+        let timeline = attack.patterns.timeline();
+        let (pattern, flash, player) = match &self.preview {
+            Some(practice) => (
+                practice.pattern().clone(),
+                practice.flash(),
+                practice.player(),
+            ),
+            None => (
+                timeline
+                    .patterns
+                    .get(self.pattern)
+                    .map_or(Pattern::empty(), |(_, pattern)| {
+                        pattern.construct(Transform::default())
+                    }),
+                Flash::Strike,
+                (Coord::ZERO, Coord::ZERO),
+            ),
+        };
+        screen.draw_playfield(
+            Flashes::Blinking {
+                pattern: &pattern,
+                flash,
+                draw_flashes: self.draw_flashes,
+            },
+            player,
+            rotation,
+            colors,
+        );
+        let beats = beats(&attack.patterns).map_or("timeline", |beats| BEATS[beats]);
+        let origin = match &attack.transform.origin {
+            origin if *origin == Origin::default() => "random",
+            origin if *origin == targeted() => "targeted",
+            _ => "custom",
+        };
+        let unsaved = if self.saved { "" } else { " (unsaved)" };
+        screen.draw_caption(
+            &format!("{}{unsaved}", attack.label(self.attack)),
+            7.0 / 8.0,
+        );
+        let pattern = match timeline.patterns.len() {
+            0 => "no patterns".to_owned(),
+            count => format!("pattern {}/{count}", self.pattern + 1),
+        };
+        screen.draw_caption(&format!("{pattern} - beats {beats}"), 13.0 / 16.0);
+        screen.draw_caption(
+            &format!(
+                "origin {origin} - transpose {} - mirror {}",
+                attack.transform.transpose, attack.transform.mirror
+            ),
+            3.0 / 4.0,
+        );
+        let help: &[&str] = if self.preview.is_some() {
+            &["previewing - Space: stop"]
+        } else {
+            &[
+                "click: toggle tile - C: clear",
+                "Up/Down: attack - Left/Right: pattern",
+                "B: beats - O: origin - T: transpose - M: mirror",
+                "Space: preview - Insert: new attack",
+            ]
+        };
+        for (k, line) in help.iter().rev().enumerate() {
+            screen.draw_caption(line, -13.0 / 16.0 + k as f32 / 16.0);
+        }
+        let status = if self.status.is_empty() {
+            "S: save - Escape: back"
+        } else {
+            &self.status
+        };
+        screen.draw_caption(status, -7.0 / 8.0);
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+
+use gilrs::{Axis, Button, Event, EventType, GamepadId, Gilrs};
+use macroquad::input::{KeyCode, is_key_pressed};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    InnerCcw,
+    InnerCw,
+    OuterCcw,
+    OuterCw,
+    Start,
+    Quit,
+    Replay,
+    Practice,
+}
+
+impl Action {
+    pub const ALL: [Self; 8] = [
+        Self::InnerCcw,
+        Self::InnerCw,
+        Self::OuterCcw,
+        Self::OuterCw,
+        Self::Start,
+        Self::Quit,
+        Self::Replay,
+        Self::Practice,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::InnerCcw => "inner ring counterclockwise",
+            Self::InnerCw => "inner ring clockwise",
+            Self::OuterCcw => "outer ring counterclockwise",
+            Self::OuterCw => "outer ring clockwise",
+            Self::Start => "start",
+            Self::Quit => "quit",
+            Self::Replay => "watch replay",
+            Self::Practice => "practice an attack",
+        }
+    }
+}
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        fn key_name(key: KeyCode) -> Option<&'static str> {
+            match key {
+                $(KeyCode::$key => Some(stringify!($key)),)*
+                _ => None,
+            }
+        }
+
+        fn key_from_name(name: &str) -> Option<KeyCode> {
+            match name {
+                $(stringify!($key) => Some(KeyCode::$key),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+key_names!(
+    Space,
+    Apostrophe,
+    Comma,
+    Minus,
+    Period,
+    Slash,
+    Key0,
+    Key1,
+    Key2,
+    Key3,
+    Key4,
+    Key5,
+    Key6,
+    Key7,
+    Key8,
+    Key9,
+    Semicolon,
+    Equal,
+    A,
+    B,
+    C,
+    D,
+    E,
+    F,
+    G,
+    H,
+    I,
+    J,
+    K,
+    L,
+    M,
+    N,
+    O,
+    P,
+    Q,
+    R,
+    S,
+    T,
+    U,
+    V,
+    W,
+    X,
+    Y,
+    Z,
+    LeftBracket,
+    Backslash,
+    RightBracket,
+    GraveAccent,
+    World1,
+    World2,
+    Enter,
+    Tab,
+    Backspace,
+    Insert,
+    Delete,
+    Right,
+    Left,
+    Down,
+    Up,
+    PageUp,
+    PageDown,
+    Home,
+    End,
+    CapsLock,
+    ScrollLock,
+    NumLock,
+    PrintScreen,
+    Pause,
+    F1,
+    F2,
+    F3,
+    F4,
+    F5,
+    F6,
+    F7,
+    F8,
+    F9,
+    F10,
+    F11,
+    F12,
+    Kp0,
+    Kp1,
+    Kp2,
+    Kp3,
+    Kp4,
+    Kp5,
+    Kp6,
+    Kp7,
+    Kp8,
+    Kp9,
+    KpDecimal,
+    KpDivide,
+    KpMultiply,
+    KpSubtract,
+    KpAdd,
+    KpEnter,
+    KpEqual,
+    LeftShift,
+    LeftControl,
+    LeftAlt,
+    LeftSuper,
+    RightShift,
+    RightControl,
+    RightAlt,
+    RightSuper,
+    Menu,
+);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Key(KeyCode);
+
+impl Key {
+    pub fn new(key: KeyCode) -> Option<Self> {
+        key_name(key).map(|_| Self(key))
+    }
+
+    pub fn name(self) -> &'static str {
+        key_name(self.0).unwrap()
+    }
+}
+
+impl TryFrom<String> for Key {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        key_from_name(&value)
+            .map(Self)
+            .ok_or_else(|| format!("unknown key {value:?}"))
+    }
+}
+
+impl From<Key> for String {
+    fn from(value: Key) -> Self {
+        value.name().into()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub inner_ccw: Key,
+    pub inner_cw: Key,
+    pub outer_ccw: Key,
+    pub outer_cw: Key,
+    pub start: Key,
+    pub quit: Key,
+    pub replay: Key,
+    pub practice: Key,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            inner_ccw: Key(KeyCode::A),
+            inner_cw: Key(KeyCode::D),
+            outer_ccw: Key(KeyCode::J),
+            outer_cw: Key(KeyCode::L),
+            start: Key(KeyCode::Space),
+            quit: Key(KeyCode::Backspace),
+            replay: Key(KeyCode::R),
+            practice: Key(KeyCode::P),
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn key(&self, action: Action) -> Key {
+        match action {
+            Action::InnerCcw => self.inner_ccw,
+            Action::InnerCw => self.inner_cw,
+            Action::OuterCcw => self.outer_ccw,
+            Action::OuterCw => self.outer_cw,
+            Action::Start => self.start,
+            Action::Quit => self.quit,
+            Action::Replay => self.replay,
+            Action::Practice => self.practice,
+        }
+    }
+
+    fn key_mut(&mut self, action: Action) -> &mut Key {
+        match action {
+            Action::InnerCcw => &mut self.inner_ccw,
+            Action::InnerCw => &mut self.inner_cw,
+            Action::OuterCcw => &mut self.outer_ccw,
+            Action::OuterCw => &mut self.outer_cw,
+            Action::Start => &mut self.start,
+            Action::Quit => &mut self.quit,
+            Action::Replay => &mut self.replay,
+            Action::Practice => &mut self.practice,
+        }
+    }
+
+    pub fn rebind(&mut self, action: Action, key: Key) {
+        let old_key = self.key(action);
+        if let Some(other) = Action::ALL.into_iter().find(|&a| self.key(a) == key) {
+            *self.key_mut(other) = old_key;
+        }
+        *self.key_mut(action) = key;
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        is_key_pressed(self.key(action).0)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default)]
+pub struct GamepadSettings {
+    pub dead_zone: f32,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self { dead_zone: 0.5 }
+    }
+}
+
+fn button_action(button: Button) -> Option<Action> {
+    match button {
+        Button::DPadLeft => Some(Action::InnerCcw),
+        Button::DPadRight => Some(Action::InnerCw),
+        Button::LeftTrigger => Some(Action::OuterCcw),
+        Button::RightTrigger => Some(Action::OuterCw),
+        Button::South | Button::Start => Some(Action::Start),
+        Button::East | Button::Select => Some(Action::Quit),
+        Button::North => Some(Action::Replay),
+        Button::West => Some(Action::Practice),
+        _ => None,
+    }
+}
+
+fn axis_actions(axis: Axis) -> Option<(Action, Action)> {
+    match axis {
+        Axis::LeftStickX | Axis::DPadX => Some((Action::InnerCcw, Action::InnerCw)),
+        Axis::RightStickX => Some((Action::OuterCcw, Action::OuterCw)),
+        _ => None,
+    }
+}
+
+pub struct Gamepads {
+    gilrs: Option<Gilrs>,
+    stick_directions: HashMap<(GamepadId, Axis), i8>,
+    pressed: Vec<Action>,
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        Self {
+            gilrs: Gilrs::new().ok(),
+            stick_directions: HashMap::new(),
+            pressed: Vec::new(),
+        }
+    }
+
+    pub fn poll(&mut self, settings: &GamepadSettings) {
+        self.pressed.clear();
+        let Some(gilrs) = &mut self.gilrs else {
+            return;
+        };
+        let dead_zone = settings.dead_zone.clamp(0.0, 0.95);
+        while let Some(Event { id, event, .. }) = gilrs.next_event() {
+            match event {
+                EventType::ButtonPressed(button, _) => {
+                    self.pressed.extend(button_action(button));
+                }
+                EventType::AxisChanged(axis, value, _) => {
+                    let Some((negative, positive)) = axis_actions(axis) else {
+                        continue;
+                    };
+                    let direction = if value < -dead_zone {
+                        -1
+                    } else if value > dead_zone {
+                        1
+                    } else {
+                        0
+                    };
+                    let previous = self.stick_directions.insert((id, axis), direction);
+                    if previous != Some(direction) {
+                        match direction {
+                            -1 => self.pressed.push(negative),
+                            1 => self.pressed.push(positive),
+                            _ => {}
+                        }
+                    }
+                }
+                EventType::Disconnected => self.stick_directions.retain(|&(i, _), _| i != id),
+                _ => {}
+            }
+        }
+    }
+
+    fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+}
+
+pub struct Input<'a> {
+    bindings: &'a KeyBindings,
+    gamepads: &'a Gamepads,
+}
+
+impl<'a> Input<'a> {
+    pub fn new(bindings: &'a KeyBindings, gamepads: &'a Gamepads) -> Self {
+        Self { bindings, gamepads }
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.bindings.pressed(action) || self.gamepads.pressed(action)
+    }
+}
@@ -1,14 +1,41 @@
-use std::{error::Error, str::FromStr};
+use std::{
+    collections::{BTreeMap, HashSet},
+    error::Error,
+    fmt, fs,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use macroquad::color::Color;
-use serde::Deserialize;
-use serde_with::{BoolFromInt, TryFromInto, serde_as};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, de, de::DeserializeOwned};
+use serde_json::Value;
+use serde_path_to_error::Segment;
+use serde_with::{TryFromInto, serde_as};
 
-use crate::playfield::PatternBlueprint;
+use crate::analysis::analyze_attack;
+use crate::notation::Tiles;
+use crate::playfield::{Coord, PatternBlueprint};
+use crate::script::{Cues, Script};
+use crate::simulation::TICKS_PER_BEAT;
 
-#[derive(Debug, Deserialize)]
+pub const SIDES: RangeInclusive<usize> = 3..=12;
+pub const DEFAULT_SIDES: usize = 6;
+const LINE_WIDTH: usize = 80;
+
+pub(crate) fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 struct SerializationColors(f32, f32, f32);
 
+impl From<Color> for SerializationColors {
+    fn from(value: Color) -> Self {
+        Self(value.r, value.g, value.b)
+    }
+}
+
 impl TryFrom<SerializationColors> for Color {
     type Error = String;
 
@@ -28,8 +55,8 @@ impl TryFrom<SerializationColors> for Color {
 }
 
 #[serde_as]
-#[derive(Debug, Deserialize)]
-pub struct LevelColors<const N: usize> {
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct LevelColors<const N: usize, const M: usize> {
     #[serde_as(as = "TryFromInto<SerializationColors>")]
     pub background: Color,
     #[serde_as(as = "TryFromInto<SerializationColors>")]
@@ -40,44 +67,321 @@ pub struct LevelColors<const N: usize> {
     pub flash: Color,
     #[serde_as(as = "[TryFromInto<SerializationColors>; N]")]
     pub main: [Color; N],
+    #[serde_as(as = "Option<[TryFromInto<SerializationColors>; M]>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rings: Option<[Color; M]>,
+}
+
+impl<const N: usize, const M: usize> LevelColors<N, M> {
+    pub fn ring(&self, o: Coord<M>) -> Color {
+        match self.rings {
+            Some(rings) => rings[o.inner()],
+            None => self.main[o.inner() % N],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepAction {
+    Warn(usize),
+    Strike(usize),
+    Rest,
 }
 
-#[derive(Clone, Debug, Deserialize)]
-#[serde(from = "SerializationAttackPatterns<N>")]
-pub enum AttackPatterns<const N: usize> {
-    Four([PatternBlueprint<N>; 1]),
-    FourPlusFour([PatternBlueprint<N>; 3]),
-    Eight([PatternBlueprint<N>; 1]),
-    EightPlusEight([PatternBlueprint<N>; 6]),
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub action: StepAction,
+    pub ticks: u64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct Timeline<const N: usize, const M: usize> {
+    pub patterns: Vec<(String, PatternBlueprint<N, M>)>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttackPatterns<const N: usize, const M: usize> {
+    Four([PatternBlueprint<N, M>; 1]),
+    FourPlusFour([PatternBlueprint<N, M>; 3]),
+    Eight([PatternBlueprint<N, M>; 1]),
+    EightPlusEight([PatternBlueprint<N, M>; 6]),
+    Timeline(Timeline<N, M>),
+}
+
+impl<const N: usize, const M: usize> AttackPatterns<N, M> {
+    pub fn timeline(&self) -> Timeline<N, M> {
+        use StepAction::{Rest, Strike, Warn};
+        let shorthand = |patterns: &[PatternBlueprint<N, M>], actions: Vec<StepAction>| Timeline {
+            patterns: patterns
+                .iter()
+                .enumerate()
+                .map(|(k, pattern)| (k.to_string(), pattern.clone()))
+                .collect(),
+            steps: actions
+                .into_iter()
+                .map(|action| Step {
+                    action,
+                    ticks: TICKS_PER_BEAT,
+                })
+                .collect(),
+        };
+        match self {
+            Self::Four(patterns) => shorthand(patterns, vec![Warn(0), Warn(0), Warn(0), Strike(0)]),
+            Self::FourPlusFour(patterns) => shorthand(
+                patterns,
+                (0..3)
+                    .map(Warn)
+                    .chain([Rest])
+                    .chain((0..3).map(Strike))
+                    .chain([Rest])
+                    .collect(),
+            ),
+            Self::Eight(patterns) => shorthand(
+                patterns,
+                [Warn(0); 7].into_iter().chain([Strike(0)]).collect(),
+            ),
+            Self::EightPlusEight(patterns) => shorthand(
+                patterns,
+                (0..6)
+                    .map(Warn)
+                    .chain([Rest, Rest])
+                    .chain((0..6).map(Strike))
+                    .chain([Rest, Rest])
+                    .collect(),
+            ),
+            Self::Timeline(timeline) => timeline.clone(),
+        }
+    }
+}
+
+impl<'de, const N: usize, const M: usize> Deserialize<'de> for AttackPatterns<N, M> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        if value.get("timeline").is_some() {
+            let timeline =
+                SerializationTimeline::<N, M>::deserialize(value).map_err(de::Error::custom)?;
+            Ok(Self::Timeline(
+                timeline.try_into().map_err(de::Error::custom)?,
+            ))
+        } else {
+            SerializationAttackPatterns::<N, M>::deserialize(value)
+                .map(Into::into)
+                .map_err(de::Error::custom)
+        }
+    }
+}
+
+impl<const N: usize, const M: usize> Serialize for AttackPatterns<N, M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use AttackPatterns as AP;
+        use SerializationAttackPatterns as SAP;
+        match self {
+            AP::Four(patterns) => SAP::Four(patterns.into()).serialize(serializer),
+            AP::FourPlusFour(patterns) => SAP::FourPlusFour(patterns.into()).serialize(serializer),
+            AP::Eight(patterns) => SAP::Eight(patterns.into()).serialize(serializer),
+            AP::EightPlusEight(patterns) => {
+                SAP::EightPlusEight(patterns.into()).serialize(serializer)
+            }
+            AP::Timeline(timeline) => SerializationTimeline::from(timeline).serialize(serializer),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum SerializationStepAction {
+    Warn(String),
+    Strike(String),
+    Rest,
+}
+
+pub const MAX_STEP_BEATS: u64 = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub(crate) struct SerializationStep {
+    pub action: SerializationStepAction,
+    pub ticks: u64,
+}
+
+impl SerializationStep {
+    pub fn pattern(&self) -> Option<&str> {
+        match &self.action {
+            SerializationStepAction::Warn(name) | SerializationStepAction::Strike(name) => {
+                Some(name)
+            }
+            SerializationStepAction::Rest => None,
+        }
+    }
+}
+
+impl FromStr for SerializationStep {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let action = match words.next() {
+            Some("rest") => SerializationStepAction::Rest,
+            Some(action @ ("warn" | "strike")) => {
+                let Some(pattern) = words.next() else {
+                    return Err(format!("step \"{s}\" doesn't name a pattern"));
+                };
+                if action == "warn" {
+                    SerializationStepAction::Warn(pattern.to_owned())
+                } else {
+                    SerializationStepAction::Strike(pattern.to_owned())
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "step \"{s}\" must start with \"warn\", \"strike\" or \"rest\""
+                ));
+            }
+        };
+        let ticks = match words.next() {
+            Some(beats) => {
+                let ticks = beats
+                    .parse::<f64>()
+                    .map(|beats| beats * TICKS_PER_BEAT as f64)
+                    .ok()
+                    .filter(|ticks| {
+                        (1.0..=(MAX_STEP_BEATS * TICKS_PER_BEAT) as f64).contains(ticks)
+                            && ticks.fract() == 0.0
+                    });
+                match ticks {
+                    Some(ticks) => ticks as u64,
+                    None => {
+                        return Err(format!(
+                            "step \"{s}\" must last a positive multiple of half a beat, \
+                             up to {MAX_STEP_BEATS} beats"
+                        ));
+                    }
+                }
+            }
+            None => TICKS_PER_BEAT,
+        };
+        if words.next().is_some() {
+            return Err(format!("step \"{s}\" has too many words"));
+        }
+        Ok(Self { action, ticks })
+    }
+}
+
+impl From<SerializationStep> for String {
+    fn from(value: SerializationStep) -> Self {
+        let mut step = match value.action {
+            SerializationStepAction::Warn(name) => format!("warn {name}"),
+            SerializationStepAction::Strike(name) => format!("strike {name}"),
+            SerializationStepAction::Rest => "rest".to_owned(),
+        };
+        if value.ticks != TICKS_PER_BEAT {
+            step.push_str(&format!(" {}", value.ticks as f64 / TICKS_PER_BEAT as f64));
+        }
+        step
+    }
+}
+
+impl TryFrom<String> for SerializationStep {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+#[serde_as]
+#[derive(Debug, Deserialize, Serialize)]
+struct SerializationTimeline<const N: usize, const M: usize> {
+    #[serde_as(as = "BTreeMap<_, Tiles>")]
+    patterns: BTreeMap<String, [[bool; N]; M]>,
+    timeline: Vec<SerializationStep>,
+}
+
+impl<const N: usize, const M: usize> TryFrom<SerializationTimeline<N, M>> for Timeline<N, M> {
+    type Error = String;
+
+    fn try_from(value: SerializationTimeline<N, M>) -> Result<Self, Self::Error> {
+        // Patterns are numbered in the order they are first used, so that
+        // reversing an attack swaps its first and last patterns.
+        let mut patterns: Vec<(String, PatternBlueprint<N, M>)> = Vec::new();
+        let mut index = |name: String| match patterns.iter().position(|(n, _)| *n == name) {
+            Some(k) => Ok(k),
+            None => match value.patterns.get(&name) {
+                Some(pattern) => {
+                    patterns.push((name, PatternBlueprint::new(*pattern)));
+                    Ok(patterns.len() - 1)
+                }
+                None => Err(format!("the timeline uses an unknown pattern \"{name}\"")),
+            },
+        };
+        let mut steps = Vec::new();
+        for SerializationStep { action, ticks } in value.timeline {
+            let action = match action {
+                SerializationStepAction::Warn(name) => StepAction::Warn(index(name)?),
+                SerializationStepAction::Strike(name) => StepAction::Strike(index(name)?),
+                SerializationStepAction::Rest => StepAction::Rest,
+            };
+            steps.push(Step { action, ticks });
+        }
+        if steps.is_empty() {
+            return Err("the timeline is empty".to_owned());
+        }
+        Ok(Self { patterns, steps })
+    }
+}
+
+impl<const N: usize, const M: usize> From<&Timeline<N, M>> for SerializationTimeline<N, M> {
+    fn from(value: &Timeline<N, M>) -> Self {
+        let name = |k: usize| value.patterns[k].0.clone();
+        Self {
+            patterns: value
+                .patterns
+                .iter()
+                .map(|(name, pattern)| (name.clone(), *pattern.rows()))
+                .collect(),
+            timeline: value
+                .steps
+                .iter()
+                .map(|&Step { action, ticks }| SerializationStep {
+                    action: match action {
+                        StepAction::Warn(k) => SerializationStepAction::Warn(name(k)),
+                        StepAction::Strike(k) => SerializationStepAction::Strike(name(k)),
+                        StepAction::Rest => SerializationStepAction::Rest,
+                    },
+                    ticks,
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(tag = "beats", content = "patterns")]
-enum SerializationAttackPatterns<const N: usize> {
+enum SerializationAttackPatterns<const N: usize, const M: usize> {
     #[serde(rename = "4")]
-    Four(SerializationPatterns<N, 1>),
+    Four(SerializationPatterns<N, M, 1>),
     #[serde(rename = "4+4")]
-    FourPlusFour(SerializationPatterns<N, 3>),
+    FourPlusFour(SerializationPatterns<N, M, 3>),
     #[serde(rename = "8")]
-    Eight(SerializationPatterns<N, 1>),
+    Eight(SerializationPatterns<N, M, 1>),
     #[serde(rename = "8+8")]
-    EightPlusEight(SerializationPatterns<N, 6>),
+    EightPlusEight(SerializationPatterns<N, M, 6>),
 }
 
 #[serde_as]
-#[derive(Debug, Deserialize)]
-struct SerializationPatterns<const N: usize, const P: usize>(
-    #[serde_as(as = "[[[BoolFromInt; N]; P]; N]")] [[[bool; N]; P]; N],
+#[derive(Debug, Deserialize, Serialize)]
+struct SerializationPatterns<const N: usize, const M: usize, const P: usize>(
+    #[serde_as(as = "Tiles")] [[[bool; N]; P]; M],
 );
 
-impl<const N: usize, const P: usize> From<SerializationPatterns<N, P>>
-    for [PatternBlueprint<N>; P]
+impl<const N: usize, const M: usize, const P: usize> From<SerializationPatterns<N, M, P>>
+    for [PatternBlueprint<N, M>; P]
 {
     #[allow(clippy::needless_range_loop)]
-    fn from(value: SerializationPatterns<N, P>) -> Self {
+    fn from(value: SerializationPatterns<N, M, P>) -> Self {
         let SerializationPatterns(value) = value;
-        let mut result = [[[false; N]; N]; P];
-        for o in 0..N {
+        let mut result = [[[false; N]; M]; P];
+        for o in 0..M {
             for p in 0..P {
                 for i in 0..N {
                     result[p][o][i] = value[o][p][i];
@@ -88,8 +392,27 @@ impl<const N: usize, const P: usize> From<SerializationPatterns<N, P>>
     }
 }
 
-impl<const N: usize> From<SerializationAttackPatterns<N>> for AttackPatterns<N> {
-    fn from(value: SerializationAttackPatterns<N>) -> Self {
+impl<const N: usize, const M: usize, const P: usize> From<&[PatternBlueprint<N, M>; P]>
+    for SerializationPatterns<N, M, P>
+{
+    #[allow(clippy::needless_range_loop)]
+    fn from(value: &[PatternBlueprint<N, M>; P]) -> Self {
+        let mut result = [[[false; N]; P]; M];
+        for o in 0..M {
+            for p in 0..P {
+                for i in 0..N {
+                    result[o][p][i] = value[p].rows()[o][i];
+                }
+            }
+        }
+        Self(result)
+    }
+}
+
+impl<const N: usize, const M: usize> From<SerializationAttackPatterns<N, M>>
+    for AttackPatterns<N, M>
+{
+    fn from(value: SerializationAttackPatterns<N, M>) -> Self {
         use AttackPatterns as AP;
         use SerializationAttackPatterns as SAP;
         match value {
@@ -101,45 +424,1460 @@ impl<const N: usize> From<SerializationAttackPatterns<N>> for AttackPatterns<N>
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, Deserialize)]
-#[serde(rename_all = "lowercase")]
-pub enum Origin {
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "SerializationRingOrigin", into = "SerializationRingOrigin")]
+pub enum RingOrigin {
     #[default]
+    Random,
+    Player(isize),
+    Positions(Vec<usize>),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum SerializationRingOrigin {
+    Position(usize),
+    Positions(Vec<usize>),
+    Name(String),
+}
+
+impl From<RingOrigin> for SerializationRingOrigin {
+    fn from(value: RingOrigin) -> Self {
+        match value {
+            RingOrigin::Random => Self::Name("random".to_owned()),
+            RingOrigin::Player(0) => Self::Name("player".to_owned()),
+            RingOrigin::Player(offset) => Self::Name(format!("player{offset:+}")),
+            RingOrigin::Positions(positions) if positions.len() == 1 => {
+                Self::Position(positions[0])
+            }
+            RingOrigin::Positions(positions) => Self::Positions(positions),
+        }
+    }
+}
+
+impl TryFrom<SerializationRingOrigin> for RingOrigin {
+    type Error = String;
+
+    fn try_from(value: SerializationRingOrigin) -> Result<Self, Self::Error> {
+        match value {
+            SerializationRingOrigin::Position(position) => Ok(Self::Positions(vec![position])),
+            SerializationRingOrigin::Positions(positions) if positions.is_empty() => {
+                Err("origin positions must not be empty".to_owned())
+            }
+            SerializationRingOrigin::Positions(positions) => Ok(Self::Positions(positions)),
+            SerializationRingOrigin::Name(name) => match name.as_str() {
+                "random" => Ok(Self::Random),
+                "player" => Ok(Self::Player(0)),
+                _ => name
+                    .strip_prefix("player")
+                    .filter(|offset| offset.starts_with(['+', '-']))
+                    .and_then(|offset| offset.parse().ok())
+                    .map(Self::Player)
+                    .ok_or_else(|| {
+                        format!(
+                            "expected \"random\", \"player\", \"player+k\", \"player-k\", a \
+                             position or a list of positions, got \"{name}\""
+                        )
+                    }),
+            },
+        }
+    }
+}
+
+impl RingOrigin {
+    pub fn check(&self, sides: usize) -> Result<(), String> {
+        match self {
+            Self::Positions(positions) => match positions.iter().find(|&&p| p >= sides) {
+                Some(position) => Err(format!(
+                    "origin position {position} is out of range for a ring with {sides} sides"
+                )),
+                None => Ok(()),
+            },
+            Self::Random | Self::Player(_) => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(from = "SerializationOrigin", into = "SerializationOrigin")]
+pub struct Origin {
+    pub inner: RingOrigin,
+    pub outer: RingOrigin,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+enum SerializationOrigin {
+    Preset(OriginPreset),
+    Rings {
+        #[serde(default, skip_serializing_if = "is_default")]
+        inner: RingOrigin,
+        #[serde(default, skip_serializing_if = "is_default")]
+        outer: RingOrigin,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum OriginPreset {
     Random,
     Targeted,
 }
 
-#[derive(Clone, Debug, Default, Deserialize)]
+impl From<SerializationOrigin> for Origin {
+    fn from(value: SerializationOrigin) -> Self {
+        match value {
+            SerializationOrigin::Preset(OriginPreset::Random) => Self::default(),
+            SerializationOrigin::Preset(OriginPreset::Targeted) => Self {
+                inner: RingOrigin::Player(0),
+                outer: RingOrigin::Player(0),
+            },
+            SerializationOrigin::Rings { inner, outer } => Self { inner, outer },
+        }
+    }
+}
+
+impl From<Origin> for SerializationOrigin {
+    fn from(value: Origin) -> Self {
+        match value {
+            Origin {
+                inner: RingOrigin::Random,
+                outer: RingOrigin::Random,
+            } => Self::Preset(OriginPreset::Random),
+            Origin {
+                inner: RingOrigin::Player(0),
+                outer: RingOrigin::Player(0),
+            } => Self::Preset(OriginPreset::Targeted),
+            Origin { inner, outer } => Self::Rings { inner, outer },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
 #[serde(default)]
 pub struct TransformBlueprint {
+    #[serde(skip_serializing_if = "is_default")]
     pub origin: Origin,
+    #[serde(skip_serializing_if = "is_default")]
     pub transpose: bool,
+    #[serde(skip_serializing_if = "is_default")]
     pub mirror: bool,
+    #[serde(skip_serializing_if = "is_default")]
+    pub mirror_inner: bool,
+    #[serde(skip_serializing_if = "is_default")]
+    pub mirror_outer: bool,
+    #[serde(skip_serializing_if = "is_default")]
+    pub reverse: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum Weight {
+    Constant(f32),
+    Curve(Vec<(u64, f32)>),
+}
+
+impl Weight {
+    pub fn at(&self, beat: u64) -> f32 {
+        let points = match self {
+            Self::Constant(weight) => return *weight,
+            Self::Curve(points) => points,
+        };
+        match points.iter().position(|&(b, _)| b > beat) {
+            Some(0) => points[0].1,
+            Some(k) => {
+                let ((b0, w0), (b1, w1)) = (points[k - 1], points[k]);
+                w0 + (w1 - w0) * (beat - b0) as f32 / (b1 - b0) as f32
+            }
+            None => points.last().map_or(0.0, |&(_, w)| w),
+        }
+    }
 }
 
-#[derive(Clone, Debug, Deserialize)]
-pub struct Attack<const N: usize> {
+#[derive(Clone, Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Schedule {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<Weight>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_beat: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_beat: Option<u64>,
+}
+
+impl Schedule {
+    pub fn check(&self) -> Result<(), String> {
+        match &self.weight {
+            Some(Weight::Constant(weight)) if !(weight.is_finite() && *weight >= 0.0) => {
+                return Err(format!("weight must not be negative, got {weight}"));
+            }
+            Some(Weight::Curve(points)) => {
+                if points.is_empty() {
+                    return Err("the weight curve has no points".to_owned());
+                }
+                if let Some((_, weight)) =
+                    points.iter().find(|(_, w)| !(w.is_finite() && *w >= 0.0))
+                {
+                    return Err(format!("weights must not be negative, got {weight}"));
+                }
+                if points.windows(2).any(|pair| pair[0].0 >= pair[1].0) {
+                    return Err("the weight curve's beats must be increasing".to_owned());
+                }
+            }
+            _ => {}
+        }
+        if let (Some(min_beat), Some(max_beat)) = (self.min_beat, self.max_beat)
+            && min_beat > max_beat
+        {
+            return Err(format!("min_beat {min_beat} is after max_beat {max_beat}"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Attack<const N: usize, const M: usize> {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub name: String,
     #[serde(flatten)]
-    pub patterns: AttackPatterns<N>,
+    pub patterns: AttackPatterns<N, M>,
     #[serde(flatten)]
     pub transform: TransformBlueprint,
+    #[serde(flatten)]
+    pub schedule: Schedule,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LevelAudio {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub music: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub countdown_low: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub countdown_high: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub death: Option<PathBuf>,
 }
 
-#[derive(Debug, Deserialize)]
-pub struct Level<const N: usize> {
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct Level<const N: usize, const M: usize> {
     pub bpm: f64,
-    pub colors: LevelColors<N>,
-    pub attacks: Vec<Attack<N>>,
+    pub colors: LevelColors<N, M>,
+    #[serde(default)]
+    include: Vec<Include>,
+    // The included attacks come after the level's own ones, and are also
+    // kept here to tell where they came from.
+    #[serde(skip)]
+    imported: Vec<ImportedAttack<N, M>>,
+    #[serde(skip)]
+    libraries: Vec<PathBuf>,
+    #[serde(default)]
+    pub attacks: Vec<Attack<N, M>>,
+    #[serde(default)]
+    pub script: Option<Script<N, M>>,
+    #[serde(default)]
+    pub reduced_flashing: bool,
+    #[serde(flatten)]
+    pub audio: LevelAudio,
+}
+
+#[derive(Serialize)]
+struct SerializationLevel<'a, const N: usize, const M: usize> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sides: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inner_sides: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outer_sides: Option<usize>,
+    bpm: f64,
+    colors: &'a LevelColors<N, M>,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    include: &'a [Include],
+    attacks: &'a [Attack<N, M>],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    script: &'a Option<Script<N, M>>,
+    #[serde(skip_serializing_if = "is_default")]
+    reduced_flashing: bool,
+    #[serde(flatten)]
+    audio: &'a LevelAudio,
+}
+
+impl<const N: usize, const M: usize> Serialize for Level<N, M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // The sides aren't stored in the level, only in its type.
+        let side = |sides: usize| (sides != DEFAULT_SIDES).then_some(sides);
+        let (sides, inner_sides, outer_sides) = if N == M {
+            (side(N), None, None)
+        } else {
+            (None, side(N), side(M))
+        };
+        SerializationLevel {
+            sides,
+            inner_sides,
+            outer_sides,
+            bpm: self.bpm,
+            colors: &self.colors,
+            include: &self.include,
+            attacks: self.own_attacks(),
+            script: &self.script,
+            reduced_flashing: self.reduced_flashing,
+            audio: &self.audio,
+        }
+        .serialize(serializer)
+    }
 }
 
-impl<const N: usize> FromStr for Level<N> {
-    type Err = Box<dyn Error>;
+#[derive(Deserialize)]
+struct LevelSides {
+    sides: Option<usize>,
+    inner_sides: Option<usize>,
+    outer_sides: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum LevelError {
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Data {
+        line: usize,
+        column: usize,
+        path: String,
+        attack: Option<String>,
+        message: String,
+    },
+    Invalid {
+        path: String,
+        attack: Option<String>,
+        message: String,
+    },
+    Include {
+        path: String,
+        file: PathBuf,
+        error: Box<LevelError>,
+    },
+}
+
+impl LevelError {
+    fn invalid(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::Invalid {
+            path: path.into(),
+            attack: None,
+            message: message.into(),
+        }
+    }
+
+    fn in_attack<const N: usize, const M: usize>(
+        k: usize,
+        attack: &Attack<N, M>,
+        field: &str,
+        message: impl Into<String>,
+    ) -> Self {
+        Self::Invalid {
+            path: format!("$.attacks[{k}]{field}"),
+            attack: (!attack.name.is_empty()).then(|| attack.name.clone()),
+            message: message.into(),
+        }
+    }
+
+    fn from_json(e: &serde_json::Error) -> Self {
+        Self::Syntax {
+            line: e.line(),
+            column: e.column(),
+            message: json_message(e),
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Syntax { .. } => "$",
+            Self::Data { path, .. } | Self::Invalid { path, .. } | Self::Include { path, .. } => {
+                path
+            }
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Self::Syntax { message, .. }
+            | Self::Data { message, .. }
+            | Self::Invalid { message, .. } => message.clone(),
+            Self::Include { file, error, .. } => format!("in {}: {error}", file.display()),
+        }
+    }
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (position, path, attack) = match self {
+            Self::Syntax { line, column, .. } => (Some((line, column)), None, None),
+            Self::Data {
+                line,
+                column,
+                path,
+                attack,
+                ..
+            } => (Some((line, column)), Some(path), attack.as_ref()),
+            Self::Invalid { path, attack, .. } => (None, Some(path), attack.as_ref()),
+            Self::Include { path, .. } => (None, Some(path), None),
+        };
+        if let Some((line, column)) = position {
+            write!(f, "line {line}, column {column}: ")?;
+        }
+        if let Some(path) = path {
+            write!(f, "{path}")?;
+            if let Some(attack) = attack {
+                write!(f, " (attack \"{attack}\")")?;
+            }
+            write!(f, ": ")?;
+        }
+        write!(f, "{}", self.message())
+    }
+}
+
+impl Error for LevelError {}
+
+// serde_json appends the position to its messages, but it's shown separately.
+fn json_message(e: &serde_json::Error) -> String {
+    let message = e.to_string();
+    let position = format!(" at line {} column {}", e.line(), e.column());
+    message
+        .strip_suffix(&position)
+        .map_or(message.clone(), str::to_owned)
+}
+
+fn json_path(path: &serde_path_to_error::Path) -> String {
+    match path.to_string().as_str() {
+        "." => "$".to_owned(),
+        path => format!("$.{path}"),
+    }
+}
+
+// Attack fields are flattened, so serde buffers them and can only report
+// errors at the attack itself. Deserializing the parts again on their own
+// finds where in the attack the error is.
+fn attack_error<const N: usize, const M: usize>(attack: &Value) -> Option<(String, String)> {
+    fn part<'de, T: Deserialize<'de>>(value: &'de Value) -> Option<(String, String)> {
+        serde_path_to_error::deserialize::<_, T>(value)
+            .err()
+            .map(|e| (e.path().to_string(), e.inner().to_string()))
+    }
+
+    let patterns = if attack.get("timeline").is_some() {
+        part::<SerializationTimeline<N, M>>(attack)
+    } else {
+        part::<SerializationAttackPatterns<N, M>>(attack)
+    };
+    patterns
+        .or_else(|| part::<TransformBlueprint>(attack))
+        .or_else(|| part::<Schedule>(attack))
+        .filter(|(path, _)| path != ".")
+}
+
+pub fn sides(s: &str) -> Result<(usize, usize), LevelError> {
+    let LevelSides {
+        sides,
+        inner_sides,
+        outer_sides,
+    } = serde_json::from_str(s).map_err(|e| LevelError::from_json(&e))?;
+    let default = sides.unwrap_or(DEFAULT_SIDES);
+    for (field, ring) in [("inner_sides", inner_sides), ("outer_sides", outer_sides)] {
+        let (field, value) = match (ring, sides) {
+            (Some(ring), _) => (field, ring),
+            (None, Some(sides)) => ("sides", sides),
+            (None, None) => continue,
+        };
+        if !SIDES.contains(&value) {
+            return Err(LevelError::invalid(
+                format!("$.{field}"),
+                format!(
+                    "must be between {} and {}, got {value}",
+                    SIDES.start(),
+                    SIDES.end()
+                ),
+            ));
+        }
+    }
+    Ok((
+        inner_sides.unwrap_or(default),
+        outer_sides.unwrap_or(default),
+    ))
+}
+
+fn deserialize_located<T: DeserializeOwned, const N: usize, const M: usize>(
+    s: &str,
+) -> Result<T, LevelError> {
+    let mut deserializer = serde_json::Deserializer::from_str(s);
+    let e = match serde_path_to_error::deserialize(&mut deserializer) {
+        Ok(level) => {
+            deserializer.end().map_err(|e| LevelError::from_json(&e))?;
+            return Ok(level);
+        }
+        Err(e) => e,
+    };
+    let inner = e.inner();
+    if !inner.is_data() {
+        return Err(LevelError::from_json(inner));
+    }
+    let mut path = json_path(e.path());
+    let mut message = json_message(inner);
+    let mut attack = None;
+    if let Some(Segment::Map { key }) = e.path().iter().next()
+        && key == "attacks"
+        && let Some(Segment::Seq { index: k }) = e.path().iter().nth(1)
+        && let Ok(document) = serde_json::from_str::<Value>(s)
+        && let Some(value) = document["attacks"].get(k)
+    {
+        attack = value["name"]
+            .as_str()
+            .filter(|name| !name.is_empty())
+            .map(str::to_owned);
+        if e.path().iter().count() == 2
+            && let Some((inner_path, inner_message)) = attack_error::<N, M>(value)
+        {
+            path = format!("{path}.{inner_path}");
+            message = inner_message;
+        }
+    }
+    Err(LevelError::Data {
+        line: inner.line(),
+        column: inner.column(),
+        path,
+        attack,
+        message,
+    })
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct Import {
+    path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    attacks: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    weight: Option<Weight>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    transform: Option<TransformBlueprint>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Include(Import);
+
+impl<'de> Deserialize<'de> for Include {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        match value {
+            Value::String(path) => Ok(Self(Import {
+                path: path.into(),
+                attacks: None,
+                weight: None,
+                transform: None,
+            })),
+            value => Import::deserialize(value)
+                .map(Self)
+                .map_err(de::Error::custom),
+        }
+    }
+}
+
+impl Serialize for Include {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.0 {
+            Import {
+                path,
+                attacks: None,
+                weight: None,
+                transform: None,
+            } => path.serialize(serializer),
+            import => import.serialize(serializer),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Includes {
+    #[serde(default)]
+    include: Vec<Include>,
+}
+
+#[derive(Deserialize)]
+struct Library<const N: usize, const M: usize> {
+    #[serde(default)]
+    include: Vec<Include>,
+    #[serde(default)]
+    attacks: Vec<Attack<N, M>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImportedAttack<const N: usize, const M: usize> {
+    // The includes that lead from the level to the file the attack is
+    // written in, by their index and the path they were written with.
+    pub via: Vec<(usize, PathBuf)>,
+    pub file: PathBuf,
+    pub index: usize,
+    pub attack: Attack<N, M>,
+}
+
+impl<const N: usize, const M: usize> ImportedAttack<N, M> {
+    pub fn path(&self) -> String {
+        let includes: String = self
+            .via
+            .iter()
+            .map(|(j, file)| format!("$.include[{j}]: {}: ", file.display()))
+            .collect();
+        format!("{includes}$.attacks[{}]", self.index)
+    }
+
+    // Reported the same way as errors found while reading the libraries.
+    fn error(&self, field: &str, message: String) -> LevelError {
+        let error = LevelError::in_attack(self.index, &self.attack, field, message);
+        self.via
+            .iter()
+            .rev()
+            .fold(error, |error, (j, file)| LevelError::Include {
+                path: format!("$.include[{j}]"),
+                file: file.clone(),
+                error: Box::new(error),
+            })
+    }
+}
+
+// Included files are tracked by their canonical path to find cycles, and
+// by the path they were included with to report them. The level itself is
+// at the bottom of the stack.
+struct IncludeWalk {
+    stack: Vec<(PathBuf, PathBuf)>,
+    libraries: Vec<PathBuf>,
+}
+
+fn read_library<const N: usize, const M: usize>(
+    file: &Path,
+    walk: &mut IncludeWalk,
+) -> Result<Vec<ImportedAttack<N, M>>, LevelError> {
+    let source = fs::read_to_string(file)
+        .map_err(|e| LevelError::invalid("$", format!("can't read file: {e}")))?;
+    if !walk.libraries.iter().any(|library| library == file) {
+        walk.libraries.push(file.to_owned());
+    }
+    let Library { include, attacks } = deserialize_located::<Library<N, M>, N, M>(&source)?;
+    let mut all: Vec<_> = attacks
+        .into_iter()
+        .enumerate()
+        .map(|(index, attack)| ImportedAttack {
+            via: Vec::new(),
+            file: file.to_owned(),
+            index,
+            attack,
+        })
+        .collect();
+    all.extend(resolve_includes(&include, file, walk)?);
+    Ok(all)
+}
+
+fn resolve_includes<const N: usize, const M: usize>(
+    includes: &[Include],
+    file: &Path,
+    walk: &mut IncludeWalk,
+) -> Result<Vec<ImportedAttack<N, M>>, LevelError> {
+    let dir = file.parent().unwrap_or(Path::new(""));
+    let mut imported = Vec::new();
+    for (j, Include(import)) in includes.iter().enumerate() {
+        let path = format!("$.include[{j}]");
+        let library = dir.join(&import.path);
+        let canonical = fs::canonicalize(&library).map_err(|e| {
+            LevelError::invalid(&path, format!("can't read {}: {e}", library.display()))
+        })?;
+        if let Some(start) = walk.stack.iter().position(|(seen, _)| *seen == canonical) {
+            let cycle: Vec<_> = walk.stack[start..]
+                .iter()
+                .map(|(_, shown)| shown)
+                .chain([&import.path])
+                .map(|file| file.display().to_string())
+                .collect();
+            return Err(LevelError::invalid(
+                &path,
+                format!("include cycle: {}", cycle.join(" -> ")),
+            ));
+        }
+        walk.stack.push((canonical.clone(), import.path.clone()));
+        let attacks = read_library::<N, M>(&canonical, walk).map_err(|e| LevelError::Include {
+            path: path.clone(),
+            file: import.path.clone(),
+            error: Box::new(e),
+        })?;
+        walk.stack.pop();
+        let attacks = match &import.attacks {
+            None => attacks,
+            Some(names) => names
+                .iter()
+                .enumerate()
+                .map(|(k, name)| {
+                    attacks
+                        .iter()
+                        .find(|imported| imported.attack.name == *name)
+                        .cloned()
+                        .ok_or_else(|| {
+                            LevelError::invalid(
+                                format!("{path}.attacks[{k}]"),
+                                format!("{} has no attack named \"{name}\"", import.path.display()),
+                            )
+                        })
+                })
+                .collect::<Result<_, _>>()?,
+        };
+        for mut attack in attacks {
+            if let Some(weight) = &import.weight {
+                attack.attack.schedule.weight = Some(weight.clone());
+            }
+            if let Some(transform) = &import.transform {
+                attack.attack.transform = transform.clone();
+            }
+            attack.via.insert(0, (j, import.path.clone()));
+            imported.push(attack);
+        }
+    }
+    Ok(imported)
+}
+
+// Reads the attacks that the level at `file` includes from attack libraries,
+// with paths relative to the file that includes them.
+pub fn include_attacks<const N: usize, const M: usize>(
+    source: &str,
+    file: &Path,
+) -> Result<Vec<ImportedAttack<N, M>>, LevelError> {
+    let Includes { include } = deserialize_located::<Includes, N, M>(source)?;
+    Ok(imports(&include, file)?.0)
+}
+
+// Also returns the canonical paths of every library that was read.
+fn imports<const N: usize, const M: usize>(
+    include: &[Include],
+    file: &Path,
+) -> Result<(Vec<ImportedAttack<N, M>>, Vec<PathBuf>), LevelError> {
+    let mut walk = IncludeWalk {
+        stack: vec![(
+            fs::canonicalize(file).unwrap_or_else(|_| file.to_owned()),
+            file.to_owned(),
+        )],
+        libraries: Vec::new(),
+    };
+    let mut imported = resolve_includes(include, file, &mut walk)?;
+    // A library that is reached more than once, like one that two other
+    // libraries both include, only adds its attacks the first time.
+    let mut seen = HashSet::new();
+    imported
+        .retain(|attack: &ImportedAttack<N, M>| seen.insert((attack.file.clone(), attack.index)));
+    Ok((imported, walk.libraries))
+}
+
+fn check_attack<const N: usize, const M: usize>(
+    attack: &Attack<N, M>,
+) -> Result<(), (&'static str, String)> {
+    if attack.transform.transpose && N != M {
+        return Err((
+            ".transpose",
+            "can't be transposed, since the inner and outer rings have different numbers \
+             of sides"
+                .to_owned(),
+        ));
+    }
+    let origin = &attack.transform.origin;
+    for (ring, origin, sides) in [("inner", &origin.inner, N), ("outer", &origin.outer, M)] {
+        origin
+            .check(sides)
+            .map_err(|e| (".origin", format!("invalid {ring} origin: {e}")))?;
+    }
+    attack
+        .schedule
+        .check()
+        .map_err(|e| ("", format!("invalid schedule: {e}")))?;
+    if analyze_attack(attack).is_impossible() {
+        return Err(("", "the attack can't always be survived".to_owned()));
+    }
+    Ok(())
+}
+
+impl<const N: usize, const M: usize> Level<N, M> {
+    fn parse(s: &str) -> Result<Self, LevelError> {
+        let (inner_sides, outer_sides) = sides(s)?;
+        if (inner_sides, outer_sides) != (N, M) {
+            return Err(LevelError::invalid(
+                "$",
+                format!(
+                    "expected a level with {N} inner and {M} outer sides, \
+                     got {inner_sides} and {outer_sides}"
+                ),
+            ));
+        }
+        deserialize_located::<Self, N, M>(s)
+    }
+
+    // Imported attacks come after the level's own attacks.
+    fn check(&self) -> Result<(), LevelError> {
+        if self.bpm <= 0.0 {
+            return Err(LevelError::invalid(
+                "$.bpm",
+                format!("bpm must be positive, got {}", self.bpm),
+            ));
+        }
+        let own = self.own_attacks().len();
+        let error = |k: usize, field: &str, message: String| match k
+            .checked_sub(own)
+            .and_then(|j| self.imported.get(j))
+        {
+            Some(imported) => imported.error(field, message),
+            None => LevelError::in_attack(k, &self.attacks[k], field, message),
+        };
+        for (k, attack) in self.attacks.iter().enumerate() {
+            if !attack.name.is_empty()
+                && self.attacks[..k]
+                    .iter()
+                    .any(|other| other.name == attack.name)
+            {
+                return Err(error(
+                    k,
+                    ".name",
+                    format!("attack name \"{}\" is used more than once", attack.name),
+                ));
+            }
+            check_attack(attack).map_err(|(field, message)| error(k, field, message))?;
+        }
+        if let Some(script) = &self.script {
+            script
+                .cues(&self.attacks)
+                .map_err(|e| LevelError::invalid("$.script", format!("invalid script: {e}")))?;
+            // An event's transform replaces the attack's, so the attack has
+            // to hold up with it too.
+            for (k, event) in script.events.iter().enumerate() {
+                let (Some(name), Some(transform)) = (&event.attack, &event.transform) else {
+                    continue;
+                };
+                let Some(attack) = self.attacks.iter().find(|attack| attack.name == *name) else {
+                    continue;
+                };
+                let attack = Attack {
+                    transform: transform.clone(),
+                    ..attack.clone()
+                };
+                check_attack(&attack).map_err(|(field, message)| LevelError::Invalid {
+                    path: format!("$.script.events[{k}].transform{field}"),
+                    attack: Some(name.clone()),
+                    message,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    // Loads the level at `file`, which may include attacks from libraries.
+    pub fn load(source: &str, file: &Path) -> Result<Self, LevelError> {
+        let mut level = Self::parse(source)?;
+        (level.imported, level.libraries) = imports(&level.include, file)?;
+        level.attacks.extend(
+            level
+                .imported
+                .iter()
+                .map(|imported| imported.attack.clone()),
+        );
+        level.check()?;
+        Ok(level)
+    }
+}
+
+impl<const N: usize, const M: usize> FromStr for Level<N, M> {
+    type Err = LevelError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let level: Self = serde_json::from_str(s)?;
-        if level.bpm <= 0.0 {
-            return Err(format!("bpm must be positive, got {}", level.bpm).into());
+        let level = Self::parse(s)?;
+        if !level.include.is_empty() {
+            return Err(LevelError::invalid(
+                "$.include",
+                "includes can only be used in level files",
+            ));
         }
+        level.check()?;
         Ok(level)
     }
 }
+
+// Like `serde_json::to_string_pretty`, but arrays of numbers and anything
+// short enough stay on one line, so patterns keep one row per line, and
+// whole numbers are written without a fraction.
+pub fn to_pretty_json(value: &Value) -> String {
+    fn whole_numbers(value: &mut Value) {
+        match value {
+            Value::Number(number) => {
+                if let Some(n) = number.as_f64()
+                    && number.is_f64()
+                    && n.fract() == 0.0
+                    && n.abs() < 2f64.powi(53)
+                {
+                    *number = (n as i64).into();
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(whole_numbers),
+            Value::Object(object) => object.values_mut().for_each(whole_numbers),
+            _ => {}
+        }
+    }
+
+    fn write(value: &Value, indent: usize, out: &mut String) {
+        let compact = value.to_string();
+        let inline = match value {
+            Value::Array(values) => {
+                values.iter().all(|v| !v.is_array() && !v.is_object())
+                    || indent + compact.len() <= LINE_WIDTH
+            }
+            Value::Object(_) => indent + compact.len() <= LINE_WIDTH,
+            _ => true,
+        };
+        if inline {
+            out.push_str(&compact);
+            return;
+        }
+        let padding = " ".repeat(indent + 4);
+        let entries: Vec<(Option<&String>, &Value)> = match value {
+            Value::Array(values) => values.iter().map(|value| (None, value)).collect(),
+            Value::Object(object) => object.iter().map(|(k, v)| (Some(k), v)).collect(),
+            _ => unreachable!(),
+        };
+        out.push_str(if value.is_array() { "[\n" } else { "{\n" });
+        for (k, (key, value)) in entries.iter().enumerate() {
+            out.push_str(&padding);
+            if let Some(key) = key {
+                out.push_str(&Value::String((*key).clone()).to_string());
+                out.push_str(": ");
+            }
+            write(value, indent + 4, out);
+            out.push_str(if k + 1 < entries.len() { ",\n" } else { "\n" });
+        }
+        out.push_str(&" ".repeat(indent));
+        out.push(if value.is_array() { ']' } else { '}' });
+    }
+
+    let mut value = value.clone();
+    whole_numbers(&mut value);
+    let mut out = String::new();
+    write(&value, 0, &mut out);
+    out.push('\n');
+    out
+}
+
+impl<const N: usize, const M: usize> Level<N, M> {
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        // Going through a string keeps the shortest form of `f32` numbers,
+        // which `serde_json::to_value` would widen to `f64`.
+        let value = serde_json::from_str(&serde_json::to_string(self)?)?;
+        Ok(to_pretty_json(&value))
+    }
+
+    // The attacks written in the level itself, without the included ones.
+    pub fn own_attacks(&self) -> &[Attack<N, M>] {
+        &self.attacks[..self.attacks.len() - self.imported.len()]
+    }
+
+    // The canonical paths of the libraries the level includes attacks from,
+    // directly or through other libraries.
+    pub fn libraries(&self) -> &[PathBuf] {
+        &self.libraries
+    }
+
+    pub fn cues(&self) -> Cues<N, M> {
+        self.script
+            .as_ref()
+            .and_then(|script| script.cues(&self.attacks).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn warnings(&self) -> Vec<String> {
+        self.attacks
+            .iter()
+            .enumerate()
+            .filter_map(|(k, attack)| {
+                let analysis = analyze_attack(attack);
+                analysis.is_frame_perfect(self.bpm).then(|| {
+                    format!(
+                        "{} needs {} moves per beat, which is nearly impossible at {} bpm",
+                        attack.label(k),
+                        analysis.required_moves.unwrap_or_default(),
+                        self.bpm
+                    )
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use rand::{Rng, SeedableRng, rngs::StdRng, seq::IndexedRandom};
+    use serde_json::json;
+
+    use super::*;
+
+    // A level with the given fields on top of the ones every level needs.
+    fn level_source<const N: usize, const M: usize>(fields: Value) -> String {
+        let mut level = json!({
+            "inner_sides": N,
+            "outer_sides": M,
+            "bpm": 120,
+            "colors": {
+                "background": [0, 0, 0],
+                "out_ring": [1, 1, 1],
+                "player": [0, 0, 0],
+                "flash": [1, 1, 1],
+                "main": vec![[1, 0, 0]; N],
+            },
+        });
+        level
+            .as_object_mut()
+            .unwrap()
+            .extend(fields.as_object().unwrap().clone());
+        level.to_string()
+    }
+
+    #[test]
+    fn script_transforms_are_checked() {
+        let level = |transform: Value| {
+            level_source::<3, 4>(json!({
+                "attacks": [{
+                    "name": "ring",
+                    "patterns": {"A": "ring-outer:0"},
+                    "timeline": ["warn A", "strike A"],
+                }],
+                "script": {"events": [
+                    {"beat": 0, "attack": "ring"},
+                    {"beat": 4, "attack": "ring", "transform": transform},
+                ]},
+            }))
+            .parse::<Level<3, 4>>()
+            .map_err(|e| e.to_string())
+        };
+        assert!(level(json!({"mirror": true})).is_ok());
+        assert_eq!(
+            level(json!({"transpose": true})).unwrap_err(),
+            "$.script.events[1].transform.transpose (attack \"ring\"): can't be transposed, \
+             since the inner and outer rings have different numbers of sides"
+        );
+        assert!(
+            level(json!({"origin": {"inner": [7]}}))
+                .unwrap_err()
+                .starts_with(
+                    "$.script.events[1].transform.origin (attack \"ring\"): invalid inner origin"
+                )
+        );
+    }
+
+    #[test]
+    fn step_lengths_are_capped() {
+        let ticks = |s: &str| s.parse::<SerializationStep>().map(|step| step.ticks);
+        assert_eq!(ticks("warn A"), Ok(TICKS_PER_BEAT));
+        assert_eq!(ticks("rest 0.5"), Ok(1));
+        assert_eq!(ticks("strike A 64"), Ok(64 * TICKS_PER_BEAT));
+        for step in [
+            "warn A 64.5",
+            "warn A 100000000",
+            "rest 1e19",
+            "rest inf",
+            "rest 0.25",
+        ] {
+            assert!(
+                ticks(step).unwrap_err().contains("up to 64 beats"),
+                "{step}"
+            );
+        }
+    }
+
+    // A row in any of the notations.
+    fn random_row<const N: usize>(rng: &mut StdRng) -> Value {
+        let tiles: [bool; N] = std::array::from_fn(|_| rng.random());
+        match rng.random_range(0..3) {
+            0 => json!(tiles.map(u8::from).to_vec()),
+            1 => json!(
+                tiles
+                    .map(|tile| if tile { '#' } else { '.' })
+                    .iter()
+                    .collect::<String>()
+            ),
+            _ => json!(format!(
+                "0x{:x}",
+                (0..N).filter(|&i| tiles[i]).map(|i| 1 << i).sum::<u32>()
+            )),
+        }
+    }
+
+    fn random_rows<const N: usize, const M: usize>(rng: &mut StdRng) -> Value {
+        if rng.random_bool(0.25) {
+            return json!(["all", "checker", "ring-outer:0", "every:2"].choose(rng));
+        }
+        json!((0..M).map(|_| random_row::<N>(rng)).collect::<Vec<_>>())
+    }
+
+    fn random_origin<const N: usize, const M: usize>(rng: &mut StdRng) -> Value {
+        match rng.random_range(0..4) {
+            0 => json!("random"),
+            1 => json!("targeted"),
+            2 => json!({"inner": "player+1", "outer": [0, M - 1]}),
+            _ => json!({"inner": rng.random_range(0..N), "outer": "player-1"}),
+        }
+    }
+
+    fn random_attack<const N: usize, const M: usize>(rng: &mut StdRng, k: usize) -> Value {
+        let mut attack = match rng.random_range(0..5) {
+            0 => json!({"beats": "4", "patterns": (0..M)
+                .map(|_| vec![random_row::<N>(rng)])
+                .collect::<Vec<_>>()}),
+            1 => json!({"beats": "8", "patterns": "checker"}),
+            2 => json!({"beats": "4+4", "patterns": (0..M)
+                .map(|_| (0..3).map(|_| random_row::<N>(rng)).collect::<Vec<_>>())
+                .collect::<Vec<_>>()}),
+            3 => json!({"beats": "8+8", "patterns": "ring-inner:0"}),
+            _ => json!({
+                "patterns": {"A": random_rows::<N, M>(rng), "B": random_rows::<N, M>(rng)},
+                "timeline": ["warn A", "strike B 0.5", "rest 2", "warn A 1.5", "strike A"],
+            }),
+        };
+        let fields = attack.as_object_mut().unwrap();
+        fields.insert("name".to_owned(), json!(format!("attack-{k}")));
+        fields.insert("origin".to_owned(), random_origin::<N, M>(rng));
+        for flag in ["mirror", "mirror_inner", "mirror_outer", "reverse"] {
+            fields.insert(flag.to_owned(), json!(rng.random_bool(0.5)));
+        }
+        fields.insert(
+            "transpose".to_owned(),
+            json!(N == M && rng.random_bool(0.5)),
+        );
+        match rng.random_range(0..3) {
+            0 => {}
+            1 => {
+                fields.insert("weight".to_owned(), json!(0.75));
+            }
+            _ => {
+                fields.insert("weight".to_owned(), json!([[0, 1], [16, 0.25]]));
+                fields.insert("min_beat".to_owned(), json!(4));
+                fields.insert("max_beat".to_owned(), json!(64));
+            }
+        }
+        attack
+    }
+
+    fn random_level<const N: usize, const M: usize>(rng: &mut StdRng) -> String {
+        let attacks: Vec<_> = (0..rng.random_range(1..6))
+            .map(|k| random_attack::<N, M>(rng, k))
+            .collect();
+        let mut fields = json!({
+            "attacks": attacks,
+            "reduced_flashing": rng.random_bool(0.5),
+            "music": "song.ogg",
+        });
+        if rng.random_bool(0.5) {
+            fields["script"] = json!({
+                "beats_per_bar": 3,
+                "events": [
+                    {"beat": 0, "attack": "attack-0"},
+                    {"bar": 1, "attack": "attack-0", "transform": {"origin": "targeted"}},
+                    {"beat": 6, "bpm": 150},
+                    {"beat": 7, "rotation_speed": -1.5},
+                    {"beat": 8, "colors": {
+                        "background": [0.1, 0.2, 0.3],
+                        "out_ring": [1, 1, 1],
+                        "player": [0, 0, 0],
+                        "flash": [1, 1, 1],
+                        "main": vec![[0.5, 0.0, 1.0]; N],
+                        "rings": vec![[0.0, 0.25, 0.0]; M],
+                    }},
+                ],
+                "length": 12,
+                "repeat": 2,
+            });
+        }
+        level_source::<N, M>(fields)
+    }
+
+    fn round_trip<const N: usize, const M: usize>() {
+        let mut rng = StdRng::seed_from_u64(22);
+        for _ in 0..16 {
+            let source = random_level::<N, M>(&mut rng);
+            let level = Level::<N, M>::parse(&source).unwrap();
+            let json = level.to_json().unwrap();
+            assert_eq!(Level::<N, M>::parse(&json).unwrap(), level, "{json}");
+        }
+    }
+
+    #[test]
+    fn levels_survive_a_round_trip() {
+        round_trip::<3, 3>();
+        round_trip::<8, 8>();
+        round_trip::<4, 3>();
+    }
+
+    // A directory of its own under the system's temporary directory.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("level-{name}-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn library(names: &[&str]) -> String {
+        let attacks: Vec<_> = names
+            .iter()
+            .map(|name| {
+                json!({
+                    "name": name,
+                    "patterns": {"A": "ring-outer:0"},
+                    "timeline": ["warn A", "strike A"],
+                })
+            })
+            .collect();
+        json!({"attacks": attacks}).to_string()
+    }
+
+    #[test]
+    fn included_attacks_are_not_written_back() {
+        let dir = temp_dir("round-trip");
+        fs::write(dir.join("lib.json"), library(&["spin", "sweep"])).unwrap();
+        fs::write(dir.join("more.json"), library(&["zap", "zip"])).unwrap();
+        let file = dir.join("level.json");
+        let source = level_source::<6, 6>(json!({
+            "include": [
+                "lib.json",
+                {"path": "more.json", "attacks": ["zip"], "weight": 2},
+            ],
+            "attacks": [{
+                "name": "own",
+                "patterns": {"A": "ring-inner:0"},
+                "timeline": ["warn A", "strike A"],
+            }],
+        }));
+        let level = Level::<6, 6>::load(&source, &file).unwrap();
+        let names: Vec<_> = level.attacks.iter().map(|attack| &attack.name).collect();
+        assert_eq!(names, ["own", "spin", "sweep", "zip"]);
+        assert_eq!(level.own_attacks().len(), 1);
+
+        let json = level.to_json().unwrap();
+        let written: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(
+            written["include"],
+            json!(["lib.json", {"path": "more.json", "attacks": ["zip"], "weight": 2}])
+        );
+        assert_eq!(written["attacks"].as_array().unwrap().len(), 1);
+        assert_eq!(Level::<6, 6>::load(&json, &file).unwrap(), level);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn with_includes(include: &[&str], library: &str) -> String {
+        let mut value: Value = serde_json::from_str(library).unwrap();
+        value["include"] = json!(include);
+        value.to_string()
+    }
+
+    #[test]
+    fn shared_libraries_are_included_once() {
+        let dir = temp_dir("diamond");
+        fs::write(
+            dir.join("b.json"),
+            with_includes(&["d.json"], &library(&["b"])),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("c.json"),
+            with_includes(&["d.json"], &library(&["c"])),
+        )
+        .unwrap();
+        fs::write(dir.join("d.json"), library(&["d1", "d2"])).unwrap();
+        let source = level_source::<6, 6>(json!({"include": ["b.json", "c.json"]}));
+        let level = Level::<6, 6>::load(&source, &dir.join("level.json")).unwrap();
+        let names: Vec<_> = level.attacks.iter().map(|attack| &attack.name).collect();
+        assert_eq!(names, ["b", "d1", "d2", "c"]);
+        let libraries: Vec<_> = level
+            .libraries()
+            .iter()
+            .map(|file| file.file_name().unwrap())
+            .collect();
+        assert_eq!(libraries, ["b.json", "d.json", "c.json"]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_cycles_are_errors() {
+        let dir = temp_dir("cycle");
+        fs::write(
+            dir.join("a.json"),
+            with_includes(&["b.json"], &library(&["a"])),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("b.json"),
+            with_includes(&["a.json"], &library(&["b"])),
+        )
+        .unwrap();
+        let source = level_source::<6, 6>(json!({"include": ["a.json"]}));
+        let error = Level::<6, 6>::load(&source, &dir.join("level.json")).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "$.include[0]: in a.json: $.include[0]: in b.json: $.include[0]: \
+             include cycle: a.json -> b.json -> a.json"
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn include_errors_use_the_written_paths() {
+        let dir = temp_dir("include-errors");
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::create_dir_all(dir.join("shared")).unwrap();
+        fs::write(
+            dir.join("lib/b.json"),
+            with_includes(&["../shared/d.json"], &library(&["b"])),
+        )
+        .unwrap();
+        let impossible = json!({"attacks": [{
+            "name": "wall",
+            "patterns": {"A": "all"},
+            "timeline": ["warn A", "strike A"],
+        }]});
+        fs::write(dir.join("shared/d.json"), impossible.to_string()).unwrap();
+        let file = dir.join("level.json");
+
+        let source = level_source::<6, 6>(json!({"include": ["lib/b.json"]}));
+        let error = Level::<6, 6>::load(&source, &file).unwrap_err();
+        let LevelError::Include {
+            path,
+            file: b,
+            error,
+        } = &error
+        else {
+            panic!("{error}");
+        };
+        assert_eq!(
+            (path.as_str(), b.as_path()),
+            ("$.include[0]", Path::new("lib/b.json"))
+        );
+        let LevelError::Include {
+            path,
+            file: d,
+            error,
+        } = error.as_ref()
+        else {
+            panic!("{error}");
+        };
+        assert_eq!(
+            (path.as_str(), d.as_path()),
+            ("$.include[0]", Path::new("../shared/d.json"))
+        );
+        assert_eq!(error.path(), "$.attacks[0]");
+        let imported = include_attacks::<6, 6>(&source, &file).unwrap();
+        assert_eq!(
+            imported[1].path(),
+            "$.include[0]: lib/b.json: $.include[0]: ../shared/d.json: $.attacks[0]"
+        );
+
+        let source = level_source::<6, 6>(json!({
+            "include": [{"path": "lib/b.json", "attacks": ["b", "c"]}],
+        }));
+        let error = Level::<6, 6>::load(&source, &file).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "$.include[0].attacks[1]: lib/b.json has no attack named \"c\""
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn parse_error(fields: Value) -> LevelError {
+        let value: Value = serde_json::from_str(&level_source::<6, 6>(fields)).unwrap();
+        // Pretty printed, so that fields are on lines of their own.
+        let source = serde_json::to_string_pretty(&value).unwrap();
+        source.parse::<Level<6, 6>>().unwrap_err()
+    }
+
+    #[test]
+    fn errors_have_positions_and_paths() {
+        let error = "{\"bpm\": 120,".parse::<Level<6, 6>>().unwrap_err();
+        assert!(matches!(
+            error,
+            LevelError::Syntax {
+                line: 1,
+                column: 12,
+                ..
+            }
+        ));
+        assert_eq!(error.path(), "$");
+
+        let error = parse_error(json!({"bpm": "fast"}));
+        assert!(matches!(error, LevelError::Data { line: 4, .. }));
+        assert_eq!(
+            error.to_string(),
+            "line 4, column 15: $.bpm: invalid type: string \"fast\", expected f64"
+        );
+        assert_eq!(
+            parse_error(json!({"bpm": -5})).to_string(),
+            "$.bpm: bpm must be positive, got -5"
+        );
+        let error = parse_error(json!({"colors": {"background": [2, 0, 0]}}));
+        assert_eq!(error.path(), "$.colors.background");
+        assert_eq!(error.message(), "color r must be between 0 and 1, got 2");
+    }
+
+    #[test]
+    fn side_errors_name_their_field() {
+        let path = |s: &str| s.parse::<Level<6, 6>>().unwrap_err().path().to_owned();
+        assert_eq!(path("{\"sides\": 2}"), "$.sides");
+        assert_eq!(path("{\"inner_sides\": 13}"), "$.inner_sides");
+        assert_eq!(path("{\"sides\": 6, \"outer_sides\": 1}"), "$.outer_sides");
+        assert_eq!(path("{\"sides\": 5}"), "$");
+    }
+
+    #[test]
+    fn attack_errors_name_the_attack() {
+        let attack = |error: &LevelError| match error {
+            LevelError::Data { attack, .. } | LevelError::Invalid { attack, .. } => attack.clone(),
+            _ => None,
+        };
+        let error = parse_error(json!({"attacks": [
+            {"name": "fine", "patterns": {"A": "ring-outer:0"}, "timeline": ["warn A"]},
+            {"name": "short", "beats": "4", "patterns": [[[1, 0, 0]]]},
+        ]}));
+        assert!(matches!(error, LevelError::Data { .. }));
+        assert_eq!(error.path(), "$.attacks[1].patterns[0][0]");
+        assert_eq!(attack(&error).as_deref(), Some("short"));
+        assert!(
+            error
+                .to_string()
+                .contains("$.attacks[1].patterns[0][0] (attack \"short\"): invalid length 3")
+        );
+
+        let error = parse_error(json!({"attacks": [
+            {"patterns": {"A": "all"}, "timeline": ["warn A"], "origin": {"inner": "left"}},
+        ]}));
+        assert_eq!(error.path(), "$.attacks[0].origin");
+        assert_eq!(attack(&error), None);
+
+        let error = parse_error(json!({"attacks": [
+            {"name": "lost", "patterns": {"A": "all"}, "timeline": ["warn B"]},
+        ]}));
+        assert_eq!(error.path(), "$.attacks[0]");
+        assert!(error.message().contains("unknown pattern \"B\""));
+
+        let error = parse_error(json!({"attacks": [
+            {"name": "wall", "patterns": {"A": "all"}, "timeline": ["warn A", "strike A"]},
+        ]}));
+        assert_eq!(
+            error,
+            LevelError::Invalid {
+                path: "$.attacks[0]".to_owned(),
+                attack: Some("wall".to_owned()),
+                message: "the attack can't always be survived".to_owned(),
+            }
+        );
+        assert_eq!(
+            parse_error(json!({"script": {"events": [{"beat": 0, "attack": "gone"}]}})).path(),
+            "$.script"
+        );
+    }
+}
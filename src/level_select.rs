@@ -0,0 +1,193 @@
+use std::{
+    any::Any,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use in_ring_out_ring::{
+    level::{self, Level},
+    with_sides,
+};
+
+use crate::save;
+
+pub const LEVELS_DIR: &str = "levels";
+const DEFAULT_SIDES: (usize, usize) = (level::DEFAULT_SIDES, level::DEFAULT_SIDES);
+pub const LEVEL_FILE: &str = "level.json";
+
+pub struct LevelEntry {
+    pub name: String,
+    pub id: String,
+    pub dir: PathBuf,
+    pub file: PathBuf,
+    pub sides: (usize, usize),
+    // The parsed `Level<N, M>` for the entry's sides, so browsing doesn't
+    // parse it again.
+    level: Option<Rc<dyn Any>>,
+    pub error: Option<String>,
+}
+
+impl LevelEntry {
+    pub fn open(path: &Path) -> Self {
+        if path.is_file() {
+            let dir = path.parent().unwrap_or(Path::new("."));
+            let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+            let file = dir.join(path.file_name().unwrap_or(LEVEL_FILE.as_ref()));
+            Self::load(dir, file)
+        } else {
+            Self::load(path.to_path_buf(), path.join(LEVEL_FILE))
+        }
+    }
+
+    fn load(dir: PathBuf, path: PathBuf) -> Self {
+        let name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let content = fs::read_to_string(&path);
+        let mut libraries = Vec::new();
+        let (sides, level) = match &content {
+            Ok(source) => match level::sides(source) {
+                Ok(sides @ (inner_sides, outer_sides)) => {
+                    let level = with_sides!(inner_sides, outer_sides, N, M => {
+                        Level::<N, M>::load(source, &path).map(|level| {
+                            libraries = level.libraries().to_vec();
+                            Rc::new(level) as Rc<dyn Any>
+                        })
+                    });
+                    (sides, level.map_err(|e| e.to_string()))
+                }
+                Err(e) => (DEFAULT_SIDES, Err(e.to_string())),
+            },
+            Err(e) => (
+                DEFAULT_SIDES,
+                Err(format!("can't read {}: {e}", path.display())),
+            ),
+        };
+        // The attacks a level includes are as much a part of it as its own.
+        let mut content = content.unwrap_or_default();
+        for library in &libraries {
+            content += &fs::read_to_string(library).unwrap_or_default();
+        }
+        let id = save::level_id(&name, &content);
+        let (level, error) = match level {
+            Ok(level) => (Some(level), None),
+            Err(e) => (None, Some(e)),
+        };
+        Self {
+            name,
+            id,
+            dir,
+            file: path,
+            sides,
+            level,
+            error,
+        }
+    }
+
+    pub fn level<const N: usize, const M: usize>(&self) -> Result<Rc<Level<N, M>>, String> {
+        if let Some(e) = &self.error {
+            return Err(e.clone());
+        }
+        self.level
+            .clone()
+            .and_then(|level| level.downcast().ok())
+            .ok_or_else(|| format!("level {} doesn't have {N} by {M} sides", self.name))
+    }
+}
+
+pub struct LevelSelect {
+    pub levels: Vec<LevelEntry>,
+    pub selected: usize,
+}
+
+impl LevelSelect {
+    pub fn scan(dir: impl AsRef<Path>, current: Option<&str>) -> Self {
+        let levels = match fs::read_dir(dir) {
+            Ok(read_dir) => {
+                let mut levels: Vec<_> = read_dir
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(|path| path.join(LEVEL_FILE).is_file())
+                    .map(|path| {
+                        let file = path.join(LEVEL_FILE);
+                        LevelEntry::load(path, file)
+                    })
+                    .collect();
+                levels.sort_by(|a, b| a.name.cmp(&b.name));
+                levels
+            }
+            Err(_) => Vec::new(),
+        };
+        let selected = current
+            .and_then(|name| levels.iter().position(|entry| entry.name == name))
+            .unwrap_or(0);
+        Self { levels, selected }
+    }
+
+    pub fn entry(&self) -> Option<&LevelEntry> {
+        self.levels.get(self.selected)
+    }
+
+    pub fn sides(&self) -> (usize, usize) {
+        self.entry().map_or(DEFAULT_SIDES, |entry| entry.sides)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    const BETA: &str = include_str!("../levels/beta/level.json");
+
+    #[test]
+    fn open_loads_the_given_file() {
+        let dir = env::temp_dir().join(format!("level-select-open-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(LEVEL_FILE), BETA).unwrap();
+        let broken = BETA.replacen("\"bpm\": 180", "\"bpm\": -1", 1);
+        fs::write(dir.join("broken.json"), &broken).unwrap();
+
+        let entry = LevelEntry::open(&dir);
+        assert_eq!(entry.file, dir.join(LEVEL_FILE));
+        assert_eq!(entry.error, None);
+        let entry = LevelEntry::open(&dir.join(LEVEL_FILE));
+        assert_eq!(entry.error, None);
+        let level = entry.level::<6, 6>().unwrap();
+        assert!(Rc::ptr_eq(&level, &entry.level().unwrap()));
+        assert!(entry.level::<5, 6>().is_err());
+
+        let entry = LevelEntry::open(&dir.join("broken.json"));
+        assert_eq!(entry.name, dir.file_name().unwrap().to_string_lossy());
+        assert_eq!(entry.file.file_name().unwrap(), "broken.json");
+        assert_eq!(entry.id, save::level_id(&entry.name, &broken));
+        assert!(entry.error.unwrap().contains("$.bpm"));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn ids_change_with_included_libraries() {
+        let dir = env::temp_dir().join(format!("level-select-id-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let library = |pattern: &str| {
+            format!(
+                r#"{{"attacks": [{{"name": "shared", "patterns": {{"A": "{pattern}"}},
+                    "timeline": ["warn A", "strike A"]}}]}}"#
+            )
+        };
+        let level = BETA.replacen('{', r#"{"include": ["lib.json"],"#, 1);
+        fs::write(dir.join(LEVEL_FILE), &level).unwrap();
+        fs::write(dir.join("lib.json"), library("ring-outer:0")).unwrap();
+        let entry = LevelEntry::open(&dir);
+        assert_eq!(entry.error, None);
+        assert_ne!(entry.id, save::level_id(&entry.name, &level));
+
+        fs::write(dir.join("lib.json"), library("ring-outer:1")).unwrap();
+        assert_ne!(LevelEntry::open(&dir).id, entry.id);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
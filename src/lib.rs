@@ -0,0 +1,61 @@
+pub mod analysis;
+pub mod command;
+pub mod level;
+pub mod lint;
+pub mod notation;
+pub mod playfield;
+pub mod replay;
+pub mod script;
+pub mod simulation;
+
+#[macro_export]
+macro_rules! with_sides {
+    ($sides:expr, $n:ident => $body:expr) => {
+        match $sides {
+            3 => {
+                const $n: usize = 3;
+                $body
+            }
+            4 => {
+                const $n: usize = 4;
+                $body
+            }
+            5 => {
+                const $n: usize = 5;
+                $body
+            }
+            6 => {
+                const $n: usize = 6;
+                $body
+            }
+            7 => {
+                const $n: usize = 7;
+                $body
+            }
+            8 => {
+                const $n: usize = 8;
+                $body
+            }
+            9 => {
+                const $n: usize = 9;
+                $body
+            }
+            10 => {
+                const $n: usize = 10;
+                $body
+            }
+            11 => {
+                const $n: usize = 11;
+                $body
+            }
+            12 => {
+                const $n: usize = 12;
+                $body
+            }
+            sides => panic!("unsupported number of sides {sides}"),
+        }
+    };
+    ($inner:expr, $outer:expr, $n:ident, $m:ident => $body:expr) => {
+        $crate::with_sides!($inner, $n => $crate::with_sides!($outer, $m => $body))
+    };
+}
@@ -0,0 +1,1085 @@
+use std::{collections::VecDeque, fmt, path::Path};
+
+use serde_json::{Map, Value};
+
+use crate::analysis::analyze_attack;
+use crate::command::{Command, Flash};
+use crate::level::{
+    self, Attack, DEFAULT_SIDES, Level, RingOrigin, SIDES, Schedule, SerializationStep,
+    TransformBlueprint, Weight,
+};
+use crate::notation;
+use crate::playfield::{Coord, Pattern, Transform};
+use crate::with_sides;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Note => f.write_str("note"),
+            Severity::Warning => f.write_str("warning"),
+            Severity::Error => f.write_str("error"),
+        }
+    }
+}
+
+impl Severity {
+    // Errors always fail a check, and warnings only when they're denied.
+    pub fn fails(self, deny_warnings: bool) -> bool {
+        match self {
+            Severity::Error => true,
+            Severity::Warning => deny_warnings,
+            Severity::Note => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Finding {
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.severity, self.path, self.message)
+    }
+}
+
+const COLOR_FIELDS: [&str; 4] = ["background", "out_ring", "player", "flash"];
+
+#[derive(Default)]
+struct Linter {
+    findings: Vec<Finding>,
+}
+
+impl Linter {
+    fn report(&mut self, severity: Severity, path: &str, message: impl Into<String>) {
+        self.findings.push(Finding {
+            severity,
+            path: path.to_owned(),
+            message: message.into(),
+        });
+    }
+
+    fn error(&mut self, path: &str, message: impl Into<String>) {
+        self.report(Severity::Error, path, message);
+    }
+
+    fn warning(&mut self, path: &str, message: impl Into<String>) {
+        self.report(Severity::Warning, path, message);
+    }
+
+    fn note(&mut self, path: &str, message: impl Into<String>) {
+        self.report(Severity::Note, path, message);
+    }
+
+    fn array<'a>(&mut self, path: &str, value: &'a Value, len: usize) -> Option<&'a [Value]> {
+        match value {
+            Value::Array(values) if values.len() == len => Some(values),
+            Value::Array(values) => {
+                self.error(
+                    path,
+                    format!("expected {len} entries, found {}", values.len()),
+                );
+                None
+            }
+            _ => {
+                self.error(path, format!("expected an array of {len} entries"));
+                None
+            }
+        }
+    }
+
+    fn color(&mut self, path: &str, value: &Value) {
+        let Some(channels) = self.array(path, value, 3) else {
+            return;
+        };
+        for (channel, value) in channels.iter().enumerate() {
+            if !value.as_f64().is_some_and(|c| (0.0..=1.0).contains(&c)) {
+                self.error(
+                    &format!("{path}[{channel}]"),
+                    "color channels must be numbers between 0 and 1",
+                );
+            }
+        }
+    }
+
+    fn colors<const N: usize, const M: usize>(&mut self, path: &str, value: &Value) {
+        let Some(colors) = value.as_object() else {
+            self.error(path, "expected an object");
+            return;
+        };
+        for field in COLOR_FIELDS {
+            let path = format!("{path}.{field}");
+            match colors.get(field) {
+                Some(value) => self.color(&path, value),
+                None => self.error(&path, "missing color"),
+            }
+        }
+        let main_path = format!("{path}.main");
+        match colors.get("main") {
+            Some(value) => {
+                if let Some(main) = self.array(&main_path, value, N) {
+                    for (k, value) in main.iter().enumerate() {
+                        self.color(&format!("{main_path}[{k}]"), value);
+                    }
+                }
+            }
+            None => self.error(&main_path, format!("missing {N} tile colors")),
+        }
+        if let Some(value) = colors.get("rings") {
+            let path = format!("{path}.rings");
+            if let Some(rings) = self.array(&path, value, M) {
+                for (k, value) in rings.iter().enumerate() {
+                    self.color(&format!("{path}[{k}]"), value);
+                }
+            }
+        }
+    }
+
+    fn patterns<const N: usize, const M: usize>(
+        &mut self,
+        path: &str,
+        value: &Value,
+        beats: &str,
+        p: usize,
+    ) {
+        if let Some(generator) = value.as_str() {
+            self.generator::<N, M>(path, generator);
+            return;
+        }
+        let Some(outer) = self.array(path, value, M) else {
+            return;
+        };
+        for (o, value) in outer.iter().enumerate() {
+            let path = format!("{path}[{o}]");
+            let Value::Array(patterns) = value else {
+                self.error(&path, format!("expected an array of {p} patterns"));
+                continue;
+            };
+            if patterns.len() != p {
+                self.error(
+                    &path,
+                    format!(
+                        "beats {beats:?} needs {p} patterns per outer position, found {}",
+                        patterns.len()
+                    ),
+                );
+                continue;
+            }
+            for (k, value) in patterns.iter().enumerate() {
+                self.tiles::<N>(&format!("{path}[{k}]"), value);
+            }
+        }
+    }
+
+    fn generator<const N: usize, const M: usize>(&mut self, path: &str, generator: &str) {
+        if let Err(e) = notation::generate::<N, M>(generator) {
+            self.error(path, e);
+        }
+    }
+
+    fn tiles<const N: usize>(&mut self, path: &str, value: &Value) {
+        if let Some(row) = value.as_str() {
+            if let Err(e) = notation::parse_row::<N>(row) {
+                self.error(path, e);
+            }
+            return;
+        }
+        let Some(tiles) = self.array(path, value, N) else {
+            return;
+        };
+        for (i, tile) in tiles.iter().enumerate() {
+            if !matches!(tile.as_u64(), Some(0 | 1)) {
+                self.error(&format!("{path}[{i}]"), "tiles must be 0 or 1");
+            }
+        }
+    }
+
+    fn timeline<const N: usize, const M: usize>(
+        &mut self,
+        path: &str,
+        attack: &Map<String, Value>,
+    ) {
+        if attack.contains_key("beats") {
+            self.error(
+                &format!("{path}.beats"),
+                "attacks with a timeline can't also set beats",
+            );
+        }
+        let names: Vec<&String> = match attack.get("patterns") {
+            Some(Value::Object(patterns)) => {
+                for (name, value) in patterns {
+                    let path = format!("{path}.patterns.{name}");
+                    if let Some(generator) = value.as_str() {
+                        self.generator::<N, M>(&path, generator);
+                        continue;
+                    }
+                    let Some(outer) = self.array(&path, value, M) else {
+                        continue;
+                    };
+                    for (o, value) in outer.iter().enumerate() {
+                        self.tiles::<N>(&format!("{path}[{o}]"), value);
+                    }
+                }
+                patterns.keys().collect()
+            }
+            Some(_) => {
+                self.error(
+                    &format!("{path}.patterns"),
+                    "expected an object of named patterns",
+                );
+                return;
+            }
+            None => {
+                self.error(path, "attack has no patterns");
+                return;
+            }
+        };
+        let timeline = match attack.get("timeline") {
+            Some(Value::Array(timeline)) if timeline.is_empty() => {
+                self.error(&format!("{path}.timeline"), "timeline is empty");
+                return;
+            }
+            Some(Value::Array(timeline)) => timeline,
+            _ => {
+                self.error(&format!("{path}.timeline"), "expected an array of steps");
+                return;
+            }
+        };
+        let mut used = Vec::new();
+        for (k, step) in timeline.iter().enumerate() {
+            let path = format!("{path}.timeline[{k}]");
+            let Some(step) = step.as_str() else {
+                self.error(&path, r#"expected a step such as "warn A" or "rest 0.5""#);
+                continue;
+            };
+            match step.parse::<SerializationStep>() {
+                Ok(step) => {
+                    if let Some(name) = step.pattern() {
+                        if !names.iter().any(|n| *n == name) {
+                            self.error(&path, format!(r#"there is no pattern named "{name}""#));
+                        }
+                        used.push(name.to_owned());
+                    }
+                }
+                Err(e) => self.error(&path, e),
+            }
+        }
+        for name in names {
+            if !used.contains(name) {
+                self.warning(
+                    &format!("{path}.patterns.{name}"),
+                    "pattern isn't used by the timeline",
+                );
+            }
+        }
+    }
+
+    fn attack<const N: usize, const M: usize>(&mut self, path: &str, value: &Value) -> bool {
+        let Some(attack) = value.as_object() else {
+            self.error(path, "expected an object");
+            return false;
+        };
+        match attack.get("name") {
+            Some(Value::String(name)) if !name.is_empty() => {}
+            Some(Value::String(_)) => self.error(&format!("{path}.name"), "name is empty"),
+            Some(_) => self.error(&format!("{path}.name"), "expected a string"),
+            None => self.error(path, "attack has no name"),
+        }
+        let errors = self.findings.len();
+        if attack.contains_key("timeline") {
+            self.timeline::<N, M>(path, attack);
+        } else {
+            let count = match attack.get("beats").and_then(Value::as_str) {
+                Some("4" | "8") => Some(1),
+                Some("4+4") => Some(3),
+                Some("8+8") => Some(6),
+                _ => {
+                    self.error(
+                        &format!("{path}.beats"),
+                        r#"expected one of "4", "4+4", "8" or "8+8""#,
+                    );
+                    None
+                }
+            };
+            match (attack.get("patterns"), count) {
+                (Some(patterns), Some(count)) => {
+                    let beats = attack["beats"].as_str().unwrap_or_default();
+                    self.patterns::<N, M>(&format!("{path}.patterns"), patterns, beats, count);
+                }
+                (None, _) => self.error(path, "attack has no patterns"),
+                (Some(_), None) => {}
+            }
+        }
+        match attack.get("origin") {
+            Some(Value::Object(origin)) => {
+                for (ring, sides) in [("inner", N), ("outer", M)] {
+                    let Some(value) = origin.get(ring) else {
+                        continue;
+                    };
+                    let path = format!("{path}.origin.{ring}");
+                    match serde_json::from_value::<RingOrigin>(value.clone()) {
+                        Ok(ring_origin) => {
+                            if let Err(e) = ring_origin.check(sides) {
+                                self.error(&path, e);
+                            }
+                        }
+                        Err(e) => self.error(&path, e.to_string()),
+                    }
+                }
+            }
+            Some(origin) if !matches!(origin.as_str(), Some("random" | "targeted")) => {
+                self.error(
+                    &format!("{path}.origin"),
+                    r#"expected "random", "targeted" or an object with inner and outer origins"#,
+                );
+            }
+            _ => {}
+        }
+        self.schedule(path, attack);
+        for flag in [
+            "transpose",
+            "mirror",
+            "mirror_inner",
+            "mirror_outer",
+            "reverse",
+        ] {
+            if attack.get(flag).is_some_and(|value| !value.is_boolean()) {
+                self.error(&format!("{path}.{flag}"), "expected true or false");
+            }
+        }
+        if N != M && attack.get("transpose") == Some(&Value::Bool(true)) {
+            self.error(
+                &format!("{path}.transpose"),
+                "attacks can't be transposed when the inner and outer rings have different \
+                 numbers of sides",
+            );
+        }
+        self.findings.len() == errors
+    }
+
+    fn schedule(&mut self, path: &str, attack: &Map<String, Value>) {
+        let weight = |value: &Value| value.as_f64().is_some_and(|weight| weight >= 0.0);
+        match attack.get("weight") {
+            None => {}
+            Some(value @ Value::Number(_)) if weight(value) => {}
+            Some(Value::Array(points)) if !points.is_empty() => {
+                let mut previous = None;
+                for (k, point) in points.iter().enumerate() {
+                    let path = format!("{path}.weight[{k}]");
+                    let beat = point.get(0).and_then(Value::as_u64);
+                    match (point.as_array().map(Vec::len), beat, point.get(1)) {
+                        (Some(2), Some(beat), Some(value)) if weight(value) => {
+                            if previous.is_some_and(|previous| beat <= previous) {
+                                self.error(&path, "beats in a weight curve must be increasing");
+                            }
+                            previous = Some(beat);
+                        }
+                        _ => self.error(
+                            &path,
+                            "expected a [beat, weight] point with a non-negative weight",
+                        ),
+                    }
+                }
+            }
+            Some(_) => self.error(
+                &format!("{path}.weight"),
+                "expected a non-negative number or a list of [beat, weight] points",
+            ),
+        }
+        for field in ["min_beat", "max_beat"] {
+            if attack.get(field).is_some_and(|value| !value.is_u64()) {
+                self.error(&format!("{path}.{field}"), "expected a beat number");
+            }
+        }
+        if let (Some(min_beat), Some(max_beat)) = (
+            attack.get("min_beat").and_then(Value::as_u64),
+            attack.get("max_beat").and_then(Value::as_u64),
+        ) && min_beat > max_beat
+        {
+            self.error(
+                &format!("{path}.max_beat"),
+                format!("max_beat is before min_beat {min_beat}"),
+            );
+        }
+    }
+
+    fn script<const N: usize, const M: usize>(
+        &mut self,
+        value: &Value,
+        attacks: &[(String, Attack<N, M>)],
+    ) {
+        let Some(script) = value.as_object() else {
+            self.error("$.script", "expected an object");
+            return;
+        };
+        for field in ["beats_per_bar", "length", "repeat"] {
+            if script
+                .get(field)
+                .is_some_and(|value| value.as_u64().is_none_or(|n| n == 0))
+            {
+                self.error(
+                    &format!("$.script.{field}"),
+                    "expected a positive whole number",
+                );
+            }
+        }
+        let beats_per_bar = script
+            .get("beats_per_bar")
+            .and_then(Value::as_u64)
+            .unwrap_or(4);
+        let Some(events) = script.get("events").and_then(Value::as_array) else {
+            self.error("$.script.events", "expected an array of events");
+            return;
+        };
+        let mut timed_attacks = Vec::new();
+        for (k, event) in events.iter().enumerate() {
+            let path = format!("$.script.events[{k}]");
+            let Some(event) = event.as_object() else {
+                self.error(&path, "expected an object");
+                continue;
+            };
+            let beat = match (event.get("beat"), event.get("bar")) {
+                (Some(beat), None) => beat.as_u64(),
+                (None, Some(bar)) => bar.as_u64().map(|bar| bar * beats_per_bar),
+                (Some(_), Some(_)) => {
+                    self.error(&path, "an event can't set both beat and bar");
+                    continue;
+                }
+                (None, None) => {
+                    self.error(&path, "an event needs a beat or a bar");
+                    continue;
+                }
+            };
+            if beat.is_none() {
+                self.error(&path, "beat and bar must be whole numbers");
+            }
+            if let Some(name) = event.get("attack") {
+                let path = format!("{path}.attack");
+                match name.as_str() {
+                    Some(name) => match attacks.iter().find(|(_, attack)| attack.name == name) {
+                        Some((_, attack)) => {
+                            if let Some(beat) = beat {
+                                timed_attacks.push((beat, attack.beat_length(), k));
+                            }
+                        }
+                        None => self.error(&path, format!(r#"there is no attack named "{name}""#)),
+                    },
+                    None => self.error(&path, "expected an attack name"),
+                }
+            } else if event.contains_key("transform") {
+                self.error(
+                    &format!("{path}.transform"),
+                    "only events with an attack can have a transform",
+                );
+            }
+            if let Some(transform) = event.get("transform")
+                && let Err(e) = serde_json::from_value::<TransformBlueprint>(transform.clone())
+            {
+                self.error(&format!("{path}.transform"), e.to_string());
+            }
+            if event
+                .get("bpm")
+                .is_some_and(|bpm| !bpm.as_f64().is_some_and(|bpm| bpm > 0.0))
+            {
+                self.error(&format!("{path}.bpm"), "bpm must be positive");
+            }
+            if let Some(colors) = event.get("colors") {
+                self.colors::<N, M>(&format!("{path}.colors"), colors);
+            }
+            if event
+                .get("rotation_speed")
+                .is_some_and(|speed| !speed.is_number())
+            {
+                self.error(&format!("{path}.rotation_speed"), "expected a number");
+            }
+            if !["attack", "bpm", "colors", "rotation_speed"]
+                .iter()
+                .any(|field| event.contains_key(*field))
+            {
+                self.error(&path, "event doesn't do anything");
+            }
+        }
+        timed_attacks.sort();
+        for pair in timed_attacks.windows(2) {
+            let ((beat, length, k), (next_beat, _, next)) = (pair[0], pair[1]);
+            if next_beat < beat + length {
+                self.warning(
+                    &format!("$.script.events[{next}]"),
+                    format!(
+                        "attack starts before the attack of $.script.events[{k}] ends, so it \
+                         will be delayed"
+                    ),
+                );
+            }
+        }
+    }
+
+    fn level<const N: usize, const M: usize>(
+        &mut self,
+        level: &Map<String, Value>,
+    ) -> Vec<(String, Attack<N, M>)> {
+        match level.get("bpm").and_then(Value::as_f64) {
+            Some(bpm) if bpm > 0.0 => {}
+            Some(_) => self.error("$.bpm", "bpm must be positive"),
+            None => self.error("$.bpm", "expected a positive number"),
+        }
+        match level.get("colors") {
+            Some(colors) => self.colors::<N, M>("$.colors", colors),
+            None => self.error("$", "level has no colors"),
+        }
+        let attacks = match level.get("attacks") {
+            Some(Value::Array(attacks)) => attacks,
+            Some(_) => {
+                self.error("$.attacks", "expected an array");
+                return Vec::new();
+            }
+            None if level.contains_key("include") => return Vec::new(),
+            None => {
+                self.error("$", "level has no attacks");
+                return Vec::new();
+            }
+        };
+        if attacks.is_empty() && !level.contains_key("include") {
+            self.warning("$.attacks", "level has no attacks");
+        }
+        let mut parsed = Vec::new();
+        for (k, value) in attacks.iter().enumerate() {
+            let path = format!("$.attacks[{k}]");
+            if !self.attack::<N, M>(&path, value) {
+                continue;
+            }
+            match serde_json::from_value(value.clone()) {
+                Ok(attack) => parsed.push((path, attack)),
+                Err(e) => self.error(&path, e.to_string()),
+            }
+        }
+        parsed
+    }
+}
+
+fn variant<const N: usize, const M: usize>(
+    attack: &Attack<N, M>,
+    transform: Transform<N, M>,
+) -> Vec<(Pattern<N, M>, Flash, u64)> {
+    let mut commands = VecDeque::new();
+    attack.clone().enqueue(&mut commands, transform);
+    commands
+        .into_iter()
+        .filter_map(|command| match command {
+            Command::NewAttack(_, _) | Command::ChangeStyle(_) => None,
+            Command::FlashPattern(pattern, flash, ticks) => Some((pattern, flash, ticks)),
+        })
+        .collect()
+}
+
+fn shifts<const K: usize>(origin: &RingOrigin) -> Vec<Coord<K>> {
+    match origin {
+        RingOrigin::Random => Coord::iter_all().collect(),
+        RingOrigin::Player(_) => vec![Coord::ZERO],
+        RingOrigin::Positions(positions) => positions
+            .iter()
+            .flat_map(|&a| {
+                positions
+                    .iter()
+                    .map(move |&b| Coord::new(a) - Coord::new(b))
+            })
+            .collect(),
+    }
+}
+
+fn is_distinct<const N: usize, const M: usize>(
+    attack: &Attack<N, M>,
+    transform: Transform<N, M>,
+) -> bool {
+    let original = variant(attack, Transform::default());
+    let origin = &attack.transform.origin;
+    let outer_shifts = shifts::<M>(&origin.outer);
+    shifts::<N>(&origin.inner).into_iter().all(|i| {
+        outer_shifts.iter().all(|&o| {
+            variant(
+                attack,
+                Transform {
+                    origin: (i, o),
+                    ..transform
+                },
+            ) != original
+        })
+    })
+}
+
+fn check_schedule<const N: usize, const M: usize>(
+    linter: &mut Linter,
+    path: &str,
+    attack: &Attack<N, M>,
+) {
+    // Attacks only start on multiples of their length. Weights change
+    // linearly between the points of a curve and stay the same outside
+    // them, so between two neighbouring points the weight is highest at the
+    // first or the last start.
+    let length = attack.beat_length().max(1);
+    let Schedule {
+        weight,
+        min_beat,
+        max_beat,
+    } = &attack.schedule;
+    let (start, end) = (min_beat.unwrap_or(0), max_beat.unwrap_or(u64::MAX));
+    if start > end {
+        return;
+    }
+    let mut bounds = vec![start];
+    if let Some(Weight::Curve(points)) = weight {
+        bounds.extend(
+            points
+                .iter()
+                .map(|&(beat, _)| beat)
+                .filter(|&beat| start < beat && beat < end),
+        );
+    }
+    bounds.push(end);
+    let chosen = bounds.windows(2).any(|pair| {
+        let last = pair[1] - pair[1] % length;
+        pair[0]
+            .checked_next_multiple_of(length)
+            .filter(|&first| first <= last)
+            .is_some_and(|first| attack.weight(first) > 0.0 || attack.weight(last) > 0.0)
+    });
+    if !chosen {
+        linter.warning(path, "attack can never be chosen");
+    }
+}
+
+fn check_reachability<const N: usize, const M: usize>(
+    linter: &mut Linter,
+    path: &str,
+    attack: &Attack<N, M>,
+    bpm: Option<f64>,
+) {
+    let analysis = analyze_attack(attack);
+    let Some(moves) = analysis.required_moves else {
+        let ((i, o), transform) = (analysis.worst_start, analysis.worst_transform);
+        linter.error(
+            path,
+            format!(
+                "attack can't be survived by a player starting at inner {}, outer {} relative \
+                 to its origin (transpose: {}, mirror inner: {}, mirror outer: {}, reverse: {})",
+                i.inner(),
+                o.inner(),
+                transform.transpose,
+                transform.mirror_inner,
+                transform.mirror_outer,
+                transform.reverse
+            ),
+        );
+        return;
+    };
+    let moves = match moves {
+        1 => "1 move".to_owned(),
+        moves => format!("{moves} moves"),
+    };
+    linter.note(path, format!("attack needs {moves} per beat"));
+    if let Some(bpm) = bpm
+        && analysis.is_frame_perfect(bpm)
+    {
+        linter.warning(
+            path,
+            format!("attack needs {moves} per beat, which is frame-perfect at {bpm} bpm"),
+        );
+    }
+}
+
+fn check_attack<const N: usize, const M: usize>(
+    linter: &mut Linter,
+    path: &str,
+    attack: &Attack<N, M>,
+) {
+    for (name, blueprint) in &attack.patterns.timeline().patterns {
+        let tiles = Coord::iter_all()
+            .flat_map(|i| Coord::iter_all().map(move |o| blueprint[(i, o)]))
+            .filter(|&tile| tile)
+            .count();
+        if tiles == 0 {
+            linter.warning(path, format!("pattern {name} is empty"));
+        } else if tiles == N * M {
+            linter.warning(
+                path,
+                format!("pattern {name} strikes every tile, so it can't be survived"),
+            );
+        }
+    }
+    let blueprint = &attack.transform;
+    let flags = [
+        ("transpose", blueprint.transpose, "transposing"),
+        ("mirror", blueprint.mirror, "mirroring"),
+        (
+            "mirror_inner",
+            blueprint.mirror_inner,
+            "mirroring the inner ring",
+        ),
+        (
+            "mirror_outer",
+            blueprint.mirror_outer,
+            "mirroring the outer ring",
+        ),
+        ("reverse", blueprint.reverse, "reversing"),
+    ];
+    for (flag, allowed, action) in flags {
+        let transform = Transform {
+            transpose: flag == "transpose",
+            mirror_inner: matches!(flag, "mirror" | "mirror_inner"),
+            mirror_outer: matches!(flag, "mirror" | "mirror_outer"),
+            reverse: flag == "reverse",
+            ..Default::default()
+        };
+        if allowed && !is_distinct(attack, transform) {
+            linter.warning(
+                &format!("{path}.{flag}"),
+                format!("{action} doesn't produce a distinct variant"),
+            );
+        }
+    }
+}
+
+pub fn lint(source: &str, file: &Path) -> Vec<Finding> {
+    let mut linter = Linter::default();
+    let level = match serde_json::from_str::<Value>(source) {
+        Ok(Value::Object(level)) => level,
+        Ok(_) => {
+            linter.error("$", "expected an object");
+            return linter.findings;
+        }
+        Err(e) => {
+            linter.error("$", format!("invalid JSON: {e}"));
+            return linter.findings;
+        }
+    };
+    let mut sides = |field: &str, default: usize| match level.get(field) {
+        None => Some(default),
+        Some(sides) => match sides.as_u64().map(|sides| sides as usize) {
+            Some(sides) if SIDES.contains(&sides) => Some(sides),
+            _ => {
+                linter.error(
+                    &format!("$.{field}"),
+                    format!(
+                        "expected a number between {} and {}",
+                        SIDES.start(),
+                        SIDES.end()
+                    ),
+                );
+                None
+            }
+        },
+    };
+    let default = sides("sides", DEFAULT_SIDES);
+    let inner_sides = default.and_then(|default| sides("inner_sides", default));
+    let outer_sides = default.and_then(|default| sides("outer_sides", default));
+    if let (Some(inner_sides), Some(outer_sides)) = (inner_sides, outer_sides) {
+        with_sides!(inner_sides, outer_sides, N, M => {
+            lint_level::<N, M>(&mut linter, &level, source, file);
+        });
+    }
+    linter.findings
+}
+
+fn lint_level<const N: usize, const M: usize>(
+    linter: &mut Linter,
+    level: &Map<String, Value>,
+    source: &str,
+    file: &Path,
+) {
+    let mut attacks = linter.level::<N, M>(level);
+    if level.contains_key("include") {
+        match level::include_attacks::<N, M>(source, file) {
+            Ok(imported) => attacks.extend(
+                imported
+                    .into_iter()
+                    .map(|imported| (imported.path(), imported.attack)),
+            ),
+            Err(e) => linter.error(e.path(), e.message()),
+        }
+    }
+    let bpm = level
+        .get("bpm")
+        .and_then(Value::as_f64)
+        .filter(|&bpm| bpm > 0.0);
+    let scripted: Vec<&str> = level
+        .get("script")
+        .and_then(|script| script.get("events"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|event| event.get("attack")?.as_str())
+        .collect();
+    for (path, attack) in &attacks {
+        check_attack(linter, path, attack);
+        if !scripted.contains(&attack.name.as_str()) {
+            check_schedule(linter, path, attack);
+        }
+        check_reachability(linter, path, attack, bpm);
+    }
+    if let Some(script) = level.get("script") {
+        linter.script::<N, M>(script, &attacks);
+    } else if !attacks.is_empty() && attacks.iter().all(|(_, attack)| attack.weight(0) <= 0.0) {
+        linter.warning(
+            "$.attacks",
+            "no attack can start at beat 0, so the level opens with a rest",
+        );
+    }
+    for (n, (path, attack)) in attacks.iter().enumerate() {
+        if !attack.name.is_empty()
+            && let Some((other_path, _)) = attacks[..n]
+                .iter()
+                .find(|(_, other)| other.name == attack.name)
+        {
+            linter.error(
+                &format!("{path}.name"),
+                format!("name is already used by {other_path}"),
+            );
+        }
+        if let Some((other_path, _)) = attacks[..n].iter().find(|(_, other)| {
+            other.patterns == attack.patterns
+                && other.transform == attack.transform
+                && other.schedule == attack.schedule
+        }) {
+            linter.warning(path, format!("attack is a duplicate of {other_path}"));
+        }
+    }
+    if !linter
+        .findings
+        .iter()
+        .any(|finding| finding.severity == Severity::Error)
+        && let Err(e) = Level::<N, M>::load(source, file)
+    {
+        linter.error(e.path(), e.message());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn lint_attacks(attacks: Value) -> Vec<Finding> {
+        let level = json!({
+            "bpm": 120,
+            "colors": {
+                "background": [0, 0, 0],
+                "out_ring": [1, 1, 1],
+                "player": [0, 0, 0],
+                "flash": [1, 1, 1],
+                "main": vec![[1, 0, 0]; 6],
+            },
+            "attacks": attacks,
+        });
+        lint(&level.to_string(), Path::new("level.json"))
+    }
+
+    fn has(findings: &[Finding], severity: Severity, path: &str, message: &str) -> bool {
+        findings.iter().any(|finding| {
+            finding.severity == severity
+                && finding.path == path
+                && finding.message.starts_with(message)
+        })
+    }
+
+    fn attack(name: &str, patterns: Value) -> Value {
+        json!({"name": name, "patterns": patterns, "timeline": ["warn A", "strike A"]})
+    }
+
+    #[test]
+    fn pattern_dimensions_follow_beats() {
+        let findings = lint_attacks(json!([
+            {"name": "a", "beats": "4+4", "patterns": vec![[[1, 0, 0, 0, 0, 0]]; 6]},
+            {"name": "b", "beats": "4", "patterns": vec![[[1, 0, 0, 0, 0, 0]]; 5]},
+            {"name": "c", "beats": "8", "patterns": vec![[[1, 0, 0]]; 6]},
+        ]));
+        assert!(has(
+            &findings,
+            Severity::Error,
+            "$.attacks[0].patterns[5]",
+            "beats \"4+4\" needs 3 patterns per outer position, found 1"
+        ));
+        assert!(has(
+            &findings,
+            Severity::Error,
+            "$.attacks[1].patterns",
+            "expected 6 entries, found 5"
+        ));
+        assert!(has(
+            &findings,
+            Severity::Error,
+            "$.attacks[2].patterns[0][0]",
+            "expected 6 entries, found 3"
+        ));
+    }
+
+    #[test]
+    fn attacks_need_names() {
+        let findings = lint_attacks(json!([
+            {"patterns": {"A": "ring-outer:0"}, "timeline": ["warn A", "strike A"]},
+            attack("", json!({"A": "ring-outer:0"})),
+        ]));
+        assert!(has(
+            &findings,
+            Severity::Error,
+            "$.attacks[0]",
+            "attack has no name"
+        ));
+        assert!(has(
+            &findings,
+            Severity::Error,
+            "$.attacks[1].name",
+            "name is empty"
+        ));
+    }
+
+    #[test]
+    fn empty_and_full_patterns_are_reported() {
+        let findings = lint_attacks(json!([{
+            "name": "a",
+            "patterns": {"A": "none", "B": "all"},
+            "timeline": ["warn A", "strike A", "warn B", "strike B"],
+        }]));
+        assert!(has(
+            &findings,
+            Severity::Warning,
+            "$.attacks[0]",
+            "pattern A is empty"
+        ));
+        assert!(has(
+            &findings,
+            Severity::Warning,
+            "$.attacks[0]",
+            "pattern B strikes every tile"
+        ));
+        assert!(has(
+            &findings,
+            Severity::Error,
+            "$.attacks[0]",
+            "attack can't be survived"
+        ));
+    }
+
+    #[test]
+    fn duplicates_are_reported() {
+        let findings = lint_attacks(json!([
+            attack("a", json!({"A": "ring-outer:0"})),
+            attack("b", json!({"A": "ring-outer:0"})),
+            attack("b", json!({"A": "ring-inner:0"})),
+        ]));
+        assert!(has(
+            &findings,
+            Severity::Warning,
+            "$.attacks[1]",
+            "attack is a duplicate of $.attacks[0]"
+        ));
+        assert!(!has(
+            &findings,
+            Severity::Warning,
+            "$.attacks[2]",
+            "attack is a duplicate"
+        ));
+        assert!(has(
+            &findings,
+            Severity::Error,
+            "$.attacks[2].name",
+            "name is already used by $.attacks[1]"
+        ));
+    }
+
+    #[test]
+    fn flags_without_distinct_variants_are_reported() {
+        let mut ring = attack("ring", json!({"A": "ring-outer:0"}));
+        ring["mirror"] = json!(true);
+        ring["transpose"] = json!(true);
+        let mut row = [[0; 6]; 6];
+        row[0][..2].copy_from_slice(&[1, 1]);
+        let mut corner = attack("corner", json!({"A": row}));
+        corner["origin"] = json!("targeted");
+        corner["mirror"] = json!(true);
+        let findings = lint_attacks(json!([ring, corner]));
+        assert!(has(
+            &findings,
+            Severity::Warning,
+            "$.attacks[0].mirror",
+            "mirroring doesn't produce a distinct variant"
+        ));
+        assert!(
+            !findings
+                .iter()
+                .any(|finding| finding.path == "$.attacks[0].transpose")
+        );
+        assert!(
+            !findings
+                .iter()
+                .any(|finding| finding.path == "$.attacks[1].mirror")
+        );
+    }
+
+    fn never_chosen(schedule: Value) -> bool {
+        let mut scheduled = attack("a", json!({"A": "ring-outer:0"}));
+        scheduled
+            .as_object_mut()
+            .unwrap()
+            .extend(schedule.as_object().unwrap().clone());
+        has(
+            &lint_attacks(json!([scheduled])),
+            Severity::Warning,
+            "$.attacks[0]",
+            "attack can never be chosen",
+        )
+    }
+
+    #[test]
+    fn schedules_are_checked_without_walking_every_beat() {
+        assert!(!never_chosen(json!({})));
+        assert!(never_chosen(json!({"weight": 0})));
+        assert!(never_chosen(json!({"min_beat": u64::MAX})));
+        assert!(!never_chosen(
+            json!({"min_beat": u64::MAX - 1, "weight": [[0, 0], [u64::MAX, 1]]})
+        ));
+        assert!(never_chosen(
+            json!({"weight": [[0, 0], [100_000_000_000u64, 0]]})
+        ));
+        assert!(!never_chosen(
+            json!({"weight": [[0, 0], [100_000_000_000u64, 1]]})
+        ));
+        assert!(!never_chosen(json!({"weight": [[0, 0], [u64::MAX, 1]]})));
+        // The attack lasts 2 beats, so it only starts on even beats.
+        let rising = json!([[0, 0], [4, 0], [5, 1]]);
+        assert!(never_chosen(json!({"weight": rising, "max_beat": 5})));
+        assert!(!never_chosen(json!({"weight": rising, "max_beat": 6})));
+        let falling = json!([[0, 1], [4, 0], [8, 0]]);
+        assert!(!never_chosen(json!({"weight": falling, "max_beat": 5})));
+        assert!(never_chosen(json!({"weight": falling, "min_beat": 3})));
+    }
+
+    #[test]
+    fn only_errors_and_denied_warnings_fail() {
+        assert!(Severity::Error.fails(false));
+        assert!(!Severity::Warning.fails(false));
+        assert!(Severity::Warning.fails(true));
+        assert!(!Severity::Note.fails(true));
+
+        let worst = |findings: Vec<Finding>| findings.iter().map(|finding| finding.severity).max();
+        let mut empty = attack("empty", json!({"A": "none", "B": "ring-outer:0"}));
+        empty["timeline"] = json!(["warn A", "warn B", "strike B"]);
+        assert_eq!(worst(lint_attacks(json!([empty]))), Some(Severity::Warning));
+        let fine = attack("fine", json!({"A": "ring-outer:0"}));
+        assert_eq!(worst(lint_attacks(json!([fine]))), Some(Severity::Note));
+        assert_eq!(
+            worst(lint_attacks(json!([{"patterns": {}}]))),
+            Some(Severity::Error)
+        );
+    }
+}
@@ -1,55 +1,332 @@
 mod audio;
-mod command;
+mod cli;
 mod drawing;
-mod level;
-mod playfield;
+mod editor;
+mod input;
+mod level_select;
+mod save;
+mod settings;
 
-use std::collections::VecDeque;
+use std::{error::Error, path::PathBuf, process::ExitCode, rc::Rc};
 
-use audio::{Sounds, Speaker, Tick};
-use command::{Command, Flash};
-use drawing::Screen;
-use level::{Attack, Level};
+use audio::{AudioFiles, Sounds, Speaker};
+use clap::Parser;
+use cli::Args;
+use drawing::{FlashLimiter, Flashes, Screen};
+use editor::{Editor, EditorResult};
+use in_ring_out_ring::{
+    command::Flash,
+    level::Level,
+    playfield::{Coord, Pattern},
+    replay::{Playback, Replay},
+    script::Style,
+    simulation::{AttackLoop, Event, Move, Simulation, TICKS_PER_BEAT, Tick},
+    with_sides,
+};
+use input::{Action, Gamepads, Input};
+use level_select::{LEVELS_DIR, LevelEntry, LevelSelect};
 use macroquad::{
+    Window,
     color::WHITE,
-    input::{KeyCode, is_key_pressed},
+    input::{KeyCode, get_char_pressed, is_key_pressed},
     time::get_frame_time,
-    window::{clear_background, next_frame, request_new_screen_size, screen_height, screen_width},
+    window::{Conf, clear_background, next_frame, screen_height, screen_width},
 };
-use playfield::{Coord, Pattern, Transform};
-use rand::{Rng, seq::IndexedRandom};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use save::{LevelRecord, SaveData};
+use settings::{MenuResult, Settings, SettingsMenu};
 
-struct GameState<const N: usize> {
-    play_state: PlayState<N>,
-    player: (Coord<N>, Coord<N>),
+struct GameState<const N: usize, const M: usize> {
+    play_state: PlayState<N, M>,
+    player: (Coord<N>, Coord<M>),
     rotation: (f32, f32),
     rotation_speed: (f32, f32),
-    pattern: Pattern<N>,
+    pattern: Pattern<N, M>,
     flash: Flash,
     draw_flashes: bool,
+    flash_limiter: FlashLimiter<N, M>,
+    style: Style<N, M>,
     tick: Tick,
     high_score: u64,
 }
 
-enum PlayState<const N: usize> {
+enum PlayState<const N: usize, const M: usize> {
+    LevelSelect {
+        select: LevelSelect,
+        preview: Result<Rc<Level<N, M>>, String>,
+    },
+    ChangeSides(LevelSelect),
     Initial,
     Playing {
-        commands: VecDeque<Command<N>>,
-        curr_attack: Option<Attack<N>>,
-        curr_transform: Transform<N>,
+        simulation: Box<Simulation<N, M>>,
+        replay: Replay,
     },
     Death {
-        commands: VecDeque<Command<N>>,
-        attack: Option<Attack<N>>,
-        original_transform: Transform<N>,
+        demonstration: Box<AttackLoop<N, M>>,
+        replay: Replay,
+        killed_by: Option<String>,
+    },
+    Replay {
+        playback: Box<Playback<N, M>>,
+        speed: f64,
+        paused: bool,
+        seek_entry: String,
+        previous: Box<PlayState<N, M>>,
+    },
+    PracticeSelect {
+        selected: usize,
+        speed: u8,
+    },
+    Practice {
+        practice: Box<AttackLoop<N, M>>,
+        selected: usize,
+        speed: u8,
     },
-    Transition(Box<PlayState<N>>),
+    Editor(Box<Editor<N, M>>),
+    Settings {
+        menu: SettingsMenu,
+        previous: Box<PlayState<N, M>>,
+    },
+    Transition(Box<PlayState<N, M>>),
+}
+
+struct Session<const N: usize, const M: usize> {
+    name: String,
+    id: String,
+    file: PathBuf,
+    level: Rc<Level<N, M>>,
+    speaker: Speaker,
+    sounds: Sounds,
 }
 
-fn transition_state<const N: usize>(play_state: PlayState<N>) -> PlayState<N> {
+enum Startup {
+    LevelSelect(LevelSelect),
+    Level {
+        entry: Box<LevelEntry>,
+        replay: Option<Replay>,
+    },
+}
+
+impl Startup {
+    fn sides(&self) -> (usize, usize) {
+        match self {
+            Startup::LevelSelect(select) => select.sides(),
+            Startup::Level { entry, .. } => entry.sides,
+        }
+    }
+}
+
+const NO_SESSION: &str = "a level should have been selected";
+const SIDES_CHANGED: &str = "a change of sides should have ended the game loop";
+
+fn reduced_flashing<const N: usize, const M: usize>(
+    settings: &Settings,
+    session: Option<&Session<N, M>>,
+) -> bool {
+    session.is_some_and(|session| settings.reduced_flashing || session.level.reduced_flashing)
+}
+
+fn show_level_select<const N: usize, const M: usize>(select: LevelSelect) -> PlayState<N, M> {
+    if select.sides() != (N, M) {
+        return PlayState::ChangeSides(select);
+    }
+    let preview = match select.entry() {
+        Some(entry) => entry.level(),
+        None => Err("no levels".into()),
+    };
+    PlayState::LevelSelect { select, preview }
+}
+
+fn level_select_state<const N: usize, const M: usize>(current: Option<&str>) -> PlayState<N, M> {
+    show_level_select(LevelSelect::scan(LEVELS_DIR, current))
+}
+
+fn transition_state<const N: usize, const M: usize>(
+    play_state: PlayState<N, M>,
+) -> PlayState<N, M> {
     PlayState::Transition(Box::new(play_state))
 }
 
+fn replay_state<const N: usize, const M: usize>(
+    level: &Level<N, M>,
+    replay: Replay,
+    previous: PlayState<N, M>,
+) -> PlayState<N, M> {
+    PlayState::Replay {
+        playback: Box::new(Playback::new(level.attacks.clone(), level.cues(), replay)),
+        speed: 1.0,
+        paused: false,
+        seek_entry: String::new(),
+        previous: Box::new(previous),
+    }
+}
+
+fn practice_bpm<const N: usize, const M: usize>(level: &Level<N, M>, speed: u8) -> f64 {
+    level.bpm * f64::from(speed) / 100.0
+}
+
+fn load_audio<const N: usize, const M: usize>(
+    entry: &LevelEntry,
+    level: &Level<N, M>,
+    args: &Args,
+) -> Result<(Speaker, Sounds), Box<dyn Error>> {
+    let files = AudioFiles::for_level(&entry.dir, &level.audio);
+    let mut speaker = Speaker::new(level.bpm, &files.music)?;
+    speaker.set_volume(args.volume());
+    Ok((speaker, Sounds::new(&files)?))
+}
+
+fn start_session<const N: usize, const M: usize>(
+    LevelEntry { name, id, file, .. }: LevelEntry,
+    level: Rc<Level<N, M>>,
+    (mut speaker, sounds): (Speaker, Sounds),
+    save: &SaveData,
+) -> (Session<N, M>, LevelRecord) {
+    let record = save.record(&id);
+    speaker.set_music_record(record.music_record);
+    let session = Session {
+        name,
+        id,
+        file,
+        level,
+        speaker,
+        sounds,
+    };
+    (session, record)
+}
+
+fn check_level(entry: &LevelEntry) -> Result<Vec<String>, String> {
+    let (inner_sides, outer_sides) = entry.sides;
+    with_sides!(inner_sides, outer_sides, N, M => {
+        let level = entry.level::<N, M>()?;
+        AudioFiles::for_level(&entry.dir, &level.audio)
+            .check()
+            .map_err(|e| e.to_string())?;
+        Ok(level.warnings())
+    })
+}
+
+fn startup(args: &Args) -> Result<Startup, String> {
+    let replay = match &args.replay {
+        Some(path) => Some(
+            Replay::load(path).map_err(|e| format!("can't load replay {}: {e}", path.display()))?,
+        ),
+        None => None,
+    };
+    let entry = match (&args.level, &replay) {
+        (Some(path), _) => LevelEntry::open(path),
+        (None, Some(replay)) => LevelSelect::scan(LEVELS_DIR, None)
+            .levels
+            .into_iter()
+            .find(|entry| entry.id == replay.level)
+            .ok_or_else(|| format!("no level in {LEVELS_DIR} matches the replay"))?,
+        (None, None) => return Ok(Startup::LevelSelect(LevelSelect::scan(LEVELS_DIR, None))),
+    };
+    if let Some(replay) = &replay
+        && replay.level != entry.id
+    {
+        return Err(format!(
+            "the replay was recorded on a different version of level {}",
+            entry.name
+        ));
+    }
+    check_level(&entry)?;
+    if let Some(name) = &args.practice {
+        let (inner_sides, outer_sides) = entry.sides;
+        let found = with_sides!(inner_sides, outer_sides, N, M => {
+            entry.level::<N, M>()?.attacks.iter().any(|attack| attack.name == *name)
+        });
+        if !found {
+            return Err(format!(
+                "level {} has no attack named \"{name}\"",
+                entry.name
+            ));
+        }
+    }
+    Ok(Startup::Level {
+        entry: Box::new(entry),
+        replay,
+    })
+}
+
+fn initial_state<const N: usize, const M: usize>(
+    startup: Startup,
+    save: &SaveData,
+    args: &Args,
+    rng: &mut impl Rng,
+) -> (GameState<N, M>, Option<Session<N, M>>) {
+    let mut gs = GameState {
+        play_state: PlayState::Initial,
+        player: rng.random(),
+        rotation: random_rotation(rng),
+        rotation_speed: random_rotation_speed_slow(rng),
+        pattern: Pattern::empty(),
+        flash: Flash::Warn,
+        draw_flashes: false,
+        flash_limiter: FlashLimiter::new(),
+        style: Style::default(),
+        tick: Tick::Beat(0),
+        high_score: 0,
+    };
+    let (entry, replay) = match startup {
+        Startup::LevelSelect(select) => {
+            gs.play_state = show_level_select(select);
+            return (gs, None);
+        }
+        Startup::Level { entry, replay } => (*entry, replay),
+    };
+    let opened = entry.level::<N, M>().and_then(|level| {
+        Ok((
+            load_audio(&entry, &level, args).map_err(|e| e.to_string())?,
+            level,
+        ))
+    });
+    let (audio, level) = match opened {
+        Ok(opened) => opened,
+        Err(e) => {
+            eprintln!("failed to open level {}: {e}", entry.name);
+            gs.play_state = level_select_state(Some(&entry.name));
+            return (gs, None);
+        }
+    };
+    let (session, record) = start_session(entry, level, audio, save);
+    gs.high_score = record.high_score;
+    let practiced = args.practice.as_ref().and_then(|name| {
+        session
+            .level
+            .attacks
+            .iter()
+            .position(|attack| attack.name == *name)
+    });
+    if args.edit {
+        match Editor::open(&session.file, &session.level) {
+            Ok(editor) => {
+                gs.play_state = PlayState::Editor(Box::new(editor));
+                gs.rotation = (0.0, 0.0);
+                gs.rotation_speed = (0.0, 0.0);
+            }
+            Err(e) => eprintln!("failed to open the editor: {e}"),
+        }
+        return (gs, Some(session));
+    }
+    match (replay, practiced) {
+        (Some(replay), _) => {
+            gs.play_state = replay_state(&session.level, replay, PlayState::Initial);
+        }
+        (None, Some(selected)) => {
+            gs = new_practice(
+                &session,
+                args,
+                rng,
+                (selected, args.practice_speed),
+                gs.high_score,
+            );
+        }
+        (None, None) => {}
+    }
+    (gs, Some(session))
+}
+
 fn random_rotation(rng: &mut impl Rng) -> (f32, f32) {
     (rng.random_range(0.0..360.0), rng.random_range(0.0..360.0))
 }
@@ -68,176 +345,492 @@ fn random_rotation_speed_fast(rng: &mut impl Rng) -> (f32, f32) {
     )
 }
 
-#[macroquad::main("In-Ring Out-Ring")]
-async fn main() {
-    const N: usize = 6;
-    let level: Level<N> = std::fs::read_to_string("beta_level.json")
-        .unwrap()
-        .parse()
-        .unwrap();
-    request_new_screen_size(512.0, 512.0);
-    let mut speaker = Speaker::new(level.bpm).unwrap();
-    let sounds = Sounds::new().unwrap();
-    let mut rng = rand::rng();
-    let mut game_state = GameState {
-        play_state: PlayState::Initial,
-        player: rng.random(),
-        rotation: random_rotation(&mut rng),
-        rotation_speed: random_rotation_speed_slow(&mut rng),
-        pattern: Pattern::empty(),
-        flash: Flash::Warn,
-        draw_flashes: false,
-        tick: Tick::Beat(0),
-        high_score: 0,
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if let Some(path) = &args.validate {
+        return match check_level(&LevelEntry::open(path)) {
+            Ok(warnings) => {
+                for warning in warnings {
+                    eprintln!("{}: warning: {warning}", path.display());
+                }
+                println!("{}: ok", path.display());
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("{}: {e}", path.display());
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let startup = match startup(&args) {
+        Ok(startup) => startup,
+        Err(e) => {
+            eprintln!("error: {e}");
+            return ExitCode::FAILURE;
+        }
     };
+    let conf = Conf {
+        window_title: "In-Ring Out-Ring".into(),
+        window_width: args.window_size.width as i32,
+        window_height: args.window_size.height as i32,
+        fullscreen: args.fullscreen,
+        ..Default::default()
+    };
+    Window::from_config(conf, run(startup, args));
+    ExitCode::SUCCESS
+}
+
+async fn run(mut startup: Startup, args: Args) {
+    let mut rng = rand::rng();
+    let mut save = SaveData::load();
+    let mut settings = Settings::load();
+    let mut gamepads = Gamepads::new();
     loop {
-        game_state = update(game_state, &level, &mut speaker, &sounds, &mut rng);
+        let (inner_sides, outer_sides) = startup.sides();
+        startup = with_sides!(inner_sides, outer_sides, N, M => {
+            run_level::<N, M>(startup, &mut save, &mut settings, &mut gamepads, &args, &mut rng).await
+        });
+    }
+}
+
+async fn run_level<const N: usize, const M: usize>(
+    startup: Startup,
+    save: &mut SaveData,
+    settings: &mut Settings,
+    gamepads: &mut Gamepads,
+    args: &Args,
+    rng: &mut impl Rng,
+) -> Startup {
+    let (mut game_state, mut session) = initial_state::<N, M>(startup, save, args, rng);
+    loop {
+        if let PlayState::ChangeSides(select) = game_state.play_state {
+            return Startup::LevelSelect(select);
+        }
+        gamepads.poll(&settings.gamepad);
+        game_state = update(
+            game_state,
+            &mut session,
+            save,
+            settings,
+            gamepads,
+            args,
+            rng,
+        );
+        if let PlayState::ChangeSides(_) = game_state.play_state {
+            continue;
+        }
         let screen = Screen::new(screen_width(), screen_height());
-        draw(&screen, &game_state, &level);
+        draw(&screen, &game_state, session.as_ref(), save, settings);
         next_frame().await;
     }
 }
 
-fn new_game<const N: usize>(rng: &mut impl Rng, high_score: u64) -> GameState<N> {
+// The state for starting to play, or to practice, from the player's
+// starting position.
+fn start_state<const N: usize, const M: usize>(
+    play_state: PlayState<N, M>,
+    player: (Coord<N>, Coord<M>),
+    rng: &mut impl Rng,
+    high_score: u64,
+) -> GameState<N, M> {
     GameState {
-        play_state: transition_state(PlayState::Playing {
-            commands: VecDeque::new(),
-            curr_attack: None,
-            curr_transform: Default::default(),
-        }),
-        player: rng.random(),
+        player,
+        play_state: transition_state(play_state),
         rotation: random_rotation(rng),
         rotation_speed: random_rotation_speed_fast(rng),
         pattern: Pattern::empty(),
         flash: Flash::Warn,
         draw_flashes: false,
+        flash_limiter: FlashLimiter::new(),
+        style: Style::default(),
         tick: Tick::Countdown(0),
         high_score,
     }
 }
 
-fn update<const N: usize>(
-    gs: GameState<N>,
-    level: &Level<N>,
-    speaker: &mut Speaker,
-    sounds: &Sounds,
+fn new_game<const N: usize, const M: usize>(
+    session: &Session<N, M>,
+    args: &Args,
+    rng: &mut impl Rng,
+    high_score: u64,
+) -> GameState<N, M> {
+    let seed = args.seed.unwrap_or_else(|| rng.random());
+    let level = &session.level;
+    let simulation = Box::new(Simulation::new(level.attacks.clone(), level.cues(), seed));
+    let player = simulation.player();
+    let play_state = PlayState::Playing {
+        simulation,
+        replay: Replay::new(session.id.clone(), seed),
+    };
+    start_state(play_state, player, rng, high_score)
+}
+
+fn new_practice<const N: usize, const M: usize>(
+    session: &Session<N, M>,
+    args: &Args,
     rng: &mut impl Rng,
-) -> GameState<N> {
+    (selected, speed): (usize, u8),
+    high_score: u64,
+) -> GameState<N, M> {
+    let seed = args.seed.unwrap_or_else(|| rng.random());
+    let practice = AttackLoop::practice(session.level.attacks[selected].clone(), seed);
+    let player = practice.player();
+    let play_state = PlayState::Practice {
+        practice: Box::new(practice),
+        selected,
+        speed,
+    };
+    start_state(play_state, player, rng, high_score)
+}
+
+fn pressed_moves(input: &Input) -> impl Iterator<Item = Move> {
+    [
+        (Action::InnerCcw, Move::InnerCcw),
+        (Action::InnerCw, Move::InnerCw),
+        (Action::OuterCcw, Move::OuterCcw),
+        (Action::OuterCw, Move::OuterCw),
+    ]
+    .into_iter()
+    .filter(|&(action, _)| input.pressed(action))
+    .map(|(_, movement)| movement)
+}
+
+// The countdown is drummed in, with a high drum on its last beat.
+fn play_tick_sounds(speaker: &mut Speaker, sounds: &Sounds, tick: Tick, countdown_length: u64) {
+    match tick {
+        Tick::Countdown(beat) if beat + 1 < countdown_length => {
+            speaker.play_sound(&sounds.low_drum).unwrap();
+        }
+        Tick::Countdown(_) => speaker.play_sound(&sounds.high_drum).unwrap(),
+        Tick::Beat(_) => {}
+    }
+}
+
+fn update<const N: usize, const M: usize>(
+    gs: GameState<N, M>,
+    session: &mut Option<Session<N, M>>,
+    save: &mut SaveData,
+    settings: &mut Settings,
+    gamepads: &Gamepads,
+    args: &Args,
+    rng: &mut impl Rng,
+) -> GameState<N, M> {
     enum GameResult {
         Playing,
-        Death,
+        Death { killed: bool },
     }
 
+    let input = Input::new(&settings.bindings, gamepads);
+    let reduced = reduced_flashing(settings, session.as_ref());
     let gs = match gs.play_state {
+        play_state @ (PlayState::LevelSelect { .. } | PlayState::Initial)
+            if is_key_pressed(KeyCode::Escape) =>
+        {
+            GameState {
+                play_state: PlayState::Settings {
+                    menu: SettingsMenu::default(),
+                    previous: Box::new(play_state),
+                },
+                ..gs
+            }
+        }
+        PlayState::LevelSelect {
+            mut select,
+            mut preview,
+        } => {
+            let count = select.levels.len();
+            let previous = select.selected;
+            if count > 0 {
+                if input.pressed(Action::InnerCcw) || input.pressed(Action::OuterCcw) {
+                    select.selected = (select.selected + count - 1) % count;
+                }
+                if input.pressed(Action::InnerCw) || input.pressed(Action::OuterCw) {
+                    select.selected = (select.selected + 1) % count;
+                }
+            }
+            let confirmed = if select.selected == previous
+                && input.pressed(Action::Start)
+                && let Ok(level) = &preview
+                && let Some(entry) = select.levels.get_mut(select.selected)
+            {
+                match load_audio(entry, level, args) {
+                    Ok(audio) => Some(audio),
+                    Err(e) => {
+                        entry.error = Some(e.to_string());
+                        preview = Err(e.to_string());
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            match (confirmed, preview) {
+                (Some(audio), Ok(level)) => {
+                    let entry = select.levels.swap_remove(select.selected);
+                    let (new_session, record) = start_session(entry, level, audio, save);
+                    *session = Some(new_session);
+                    GameState {
+                        play_state: PlayState::Initial,
+                        pattern: Pattern::empty(),
+                        style: Style::default(),
+                        tick: Tick::Beat(0),
+                        high_score: record.high_score,
+                        ..gs
+                    }
+                }
+                (_, preview) if select.selected == previous => GameState {
+                    play_state: PlayState::LevelSelect { select, preview },
+                    ..gs
+                },
+                _ => GameState {
+                    play_state: show_level_select(select),
+                    ..gs
+                },
+            }
+        }
+        PlayState::ChangeSides(_) => unreachable!("{SIDES_CHANGED}"),
         PlayState::Initial => {
-            if is_key_pressed(KeyCode::Space) {
-                new_game(rng, gs.high_score)
+            if input.pressed(Action::Start) {
+                new_game(
+                    session.as_ref().expect(NO_SESSION),
+                    args,
+                    rng,
+                    gs.high_score,
+                )
+            } else if input.pressed(Action::Quit) {
+                let name = session.take().map(|session| session.name);
+                GameState {
+                    play_state: level_select_state(name.as_deref()),
+                    ..gs
+                }
+            } else if is_key_pressed(KeyCode::Tab) {
+                let session = session.as_ref().expect(NO_SESSION);
+                match Editor::open(&session.file, &session.level) {
+                    Ok(editor) => GameState {
+                        play_state: PlayState::Editor(Box::new(editor)),
+                        rotation: (0.0, 0.0),
+                        rotation_speed: (0.0, 0.0),
+                        ..gs
+                    },
+                    Err(e) => {
+                        eprintln!("failed to open the editor: {e}");
+                        GameState {
+                            play_state: PlayState::Initial,
+                            ..gs
+                        }
+                    }
+                }
+            } else if input.pressed(Action::Practice)
+                && !session.as_ref().expect(NO_SESSION).level.attacks.is_empty()
+            {
+                GameState {
+                    play_state: PlayState::PracticeSelect {
+                        selected: 0,
+                        speed: args.practice_speed,
+                    },
+                    ..gs
+                }
             } else {
                 gs
             }
         }
+        PlayState::PracticeSelect {
+            mut selected,
+            mut speed,
+        } => {
+            let level = &session.as_ref().expect(NO_SESSION).level;
+            let count = level.attacks.len();
+            if input.pressed(Action::InnerCcw) {
+                selected = (selected + count - 1) % count;
+            }
+            if input.pressed(Action::InnerCw) {
+                selected = (selected + 1) % count;
+            }
+            if input.pressed(Action::OuterCcw) {
+                speed = speed.saturating_sub(10).max(10);
+            }
+            if input.pressed(Action::OuterCw) {
+                speed = (speed + 10).min(100);
+            }
+            if input.pressed(Action::Start) {
+                new_practice(
+                    session.as_ref().expect(NO_SESSION),
+                    args,
+                    rng,
+                    (selected, speed),
+                    gs.high_score,
+                )
+            } else if input.pressed(Action::Quit) {
+                GameState {
+                    play_state: PlayState::Initial,
+                    ..gs
+                }
+            } else {
+                GameState {
+                    play_state: PlayState::PracticeSelect { selected, speed },
+                    ..gs
+                }
+            }
+        }
+        PlayState::Practice {
+            mut practice,
+            selected,
+            speed,
+        } => {
+            let Session {
+                level,
+                speaker,
+                sounds,
+                ..
+            } = session.as_mut().expect(NO_SESSION);
+            if input.pressed(Action::Quit) {
+                return GameState {
+                    play_state: transition_state(PlayState::PracticeSelect { selected, speed }),
+                    pattern: Pattern::empty(),
+                    rotation_speed: random_rotation_speed_slow(rng),
+                    tick: Tick::Beat(0),
+                    ..gs
+                };
+            }
+            for movement in pressed_moves(&input) {
+                practice.move_player(movement);
+            }
+            speaker.set_bpm(practice_bpm(level, speed));
+            let mut tick = gs.tick;
+            let mut rotation_speed = gs.rotation_speed;
+            while speaker.process_tick() {
+                for event in practice.advance() {
+                    match event {
+                        Event::Tick(next_tick) => {
+                            tick = next_tick;
+                            play_tick_sounds(speaker, sounds, tick, practice.countdown_length());
+                        }
+                        Event::NewAttack(_, _) => {
+                            rotation_speed = random_rotation_speed_fast(rng);
+                        }
+                        Event::Flash(_, _) | Event::ChangeStyle(_) => {}
+                        Event::Death => speaker.play_sound(&sounds.death).unwrap(),
+                    }
+                }
+            }
+            GameState {
+                player: practice.player(),
+                pattern: practice.pattern().clone(),
+                flash: practice.flash(),
+                draw_flashes: reduced || practice.flash_progress(speaker.tick_fraction()) < 0.5,
+                tick,
+                rotation_speed,
+                play_state: PlayState::Practice {
+                    practice,
+                    selected,
+                    speed,
+                },
+                ..gs
+            }
+        }
         PlayState::Playing {
-            mut commands,
-            mut curr_attack,
-            mut curr_transform,
+            mut simulation,
+            mut replay,
         } => {
-            let (mut player_i, mut player_o) = gs.player;
+            let Session {
+                name,
+                id,
+                level,
+                speaker,
+                sounds,
+                ..
+            } = session.as_mut().expect(NO_SESSION);
             let mut tick = gs.tick;
             let mut high_score = gs.high_score;
-            let mut pattern = gs.pattern;
-            let mut flash = gs.flash;
             let rotation = gs.rotation;
             let mut rotation_speed = gs.rotation_speed;
+            let flash_limiter = gs.flash_limiter;
             #[allow(unused_variables)]
             let gs = ();
-            if is_key_pressed(KeyCode::A) {
-                player_i = player_i - Coord::ONE;
-            }
-            if is_key_pressed(KeyCode::D) {
-                player_i = player_i + Coord::ONE;
-            }
-            if is_key_pressed(KeyCode::J) {
-                player_o = player_o - Coord::ONE;
+            for movement in pressed_moves(&input) {
+                simulation.move_player(movement);
+                replay.record(simulation.ticks(), speaker.time(), movement);
             }
-            if is_key_pressed(KeyCode::L) {
-                player_o = player_o + Coord::ONE;
-            }
-            let player = (player_i, player_o);
             let game_result = 'process: {
-                if is_key_pressed(KeyCode::Backspace) {
-                    break 'process GameResult::Death;
+                if input.pressed(Action::Quit) {
+                    break 'process GameResult::Death { killed: false };
                 }
-                while let Some(next_tick) = speaker.process_tick(4) {
-                    tick = next_tick;
-                    match tick {
-                        Tick::Countdown(0..3) => speaker.play_sound(&sounds.low_drum).unwrap(),
-                        Tick::Countdown(3) => speaker.play_sound(&sounds.high_drum).unwrap(),
-                        Tick::Countdown(_) => unreachable!(),
-                        Tick::Beat(beat) => {
-                            if beat == 0 {
-                                speaker.play_music(rng).unwrap();
-                            }
-                            speaker.update_music_progress(beat);
-                            high_score = high_score.max(beat);
-                            if commands.is_empty() && !level.attacks.is_empty() {
-                                let attack = level
-                                    .attacks
-                                    .choose_weighted(rng, |attack| {
-                                        if beat % attack.beat_length() == 0 {
-                                            attack.weight()
-                                        } else {
-                                            0.0
-                                        }
-                                    })
-                                    .unwrap()
-                                    .clone();
-                                let transform = attack.transform.construct(rng, player);
-                                attack.enqueue(&mut commands, transform);
-                            }
-                            loop {
-                                let Some(command) = commands.pop_front() else {
-                                    break;
-                                };
-                                match command {
-                                    Command::NewAttack(attack, transform) => {
-                                        curr_attack = Some(attack);
-                                        curr_transform = transform;
-                                        rotation_speed = random_rotation_speed_fast(rng);
-                                    }
-                                    Command::FlashPattern(attack_pattern, attack_flash) => {
-                                        pattern = attack_pattern;
-                                        flash = attack_flash;
-                                        if flash == Flash::Strike && pattern[(player_i, player_o)] {
-                                            break 'process GameResult::Death;
-                                        }
-                                        break;
+                while speaker.process_tick() {
+                    for event in simulation.advance() {
+                        match event {
+                            Event::Tick(next_tick) => {
+                                tick = next_tick;
+                                play_tick_sounds(
+                                    speaker,
+                                    sounds,
+                                    tick,
+                                    Simulation::<N, M>::COUNTDOWN_LENGTH,
+                                );
+                                if let Tick::Beat(beat) = tick {
+                                    if beat == 0 {
+                                        let mut music_rng =
+                                            StdRng::seed_from_u64(simulation.seed());
+                                        speaker.play_music(&mut music_rng).unwrap();
                                     }
+                                    speaker.update_music_progress(beat);
+                                    high_score = high_score.max(beat);
                                 }
                             }
+                            Event::NewAttack(attack, _) => {
+                                replay.record_attack(simulation.ticks() - 1, &attack.name);
+                                rotation_speed = random_rotation_speed_fast(rng);
+                            }
+                            Event::Flash(_, _) | Event::ChangeStyle(_) => {}
+                            Event::Death => break 'process GameResult::Death { killed: true },
                         }
                     }
                 }
                 GameResult::Playing
             };
+            speaker.set_bpm(simulation.style().bpm.unwrap_or(level.bpm));
+            let player = simulation.player();
+            let pattern = simulation.pattern().clone();
+            let flash = simulation.flash();
+            let flash_progress = simulation.flash_progress(speaker.tick_fraction());
+            let style = simulation.style().clone();
             let (play_state, draw_flashes) = match game_result {
                 GameResult::Playing => (
-                    PlayState::Playing {
-                        commands,
-                        curr_attack,
-                        curr_transform,
-                    },
-                    speaker.beat_fraction() < 0.5,
+                    PlayState::Playing { simulation, replay },
+                    reduced || flash_progress < 0.5,
                 ),
-                GameResult::Death => {
+                GameResult::Death { killed } => {
+                    let record = save.record_mut(id);
+                    record.high_score = record.high_score.max(high_score);
+                    record.music_record = speaker.music_record();
+                    record.plays += 1;
+                    let killed_by = simulation
+                        .attack()
+                        .filter(|_| killed)
+                        .map(|attack| attack.name.clone());
+                    if killed {
+                        record.deaths += 1;
+                    }
+                    if let Some(name) = &killed_by
+                        && !name.is_empty()
+                    {
+                        *record.attack_deaths.entry(name.clone()).or_default() += 1;
+                    }
+                    if let Err(e) = save.store() {
+                        eprintln!("failed to save records: {e}");
+                    }
+                    replay.finish(simulation.ticks());
+                    if let Err(e) = save::store_replay(&replay, name) {
+                        eprintln!("failed to save replay: {e}");
+                    }
                     rotation_speed = random_rotation_speed_slow(rng);
                     speaker.play_sound(&sounds.death).unwrap();
                     speaker.stop_music();
                     speaker.restart_clock();
                     (
                         transition_state(PlayState::Death {
-                            commands: VecDeque::new(),
-                            attack: curr_attack,
-                            original_transform: curr_transform,
+                            demonstration: Box::new(simulation.into_demonstration()),
+                            replay,
+                            killed_by,
                         }),
                         true,
                     )
@@ -251,93 +844,287 @@ fn update<const N: usize>(
                 pattern,
                 flash,
                 draw_flashes,
+                flash_limiter,
+                style,
                 tick,
                 high_score,
             }
         }
         PlayState::Death {
-            mut commands,
-            attack,
-            original_transform,
+            mut demonstration,
+            replay,
+            killed_by,
         } => {
-            let mut pattern = gs.pattern;
-            let mut flash = gs.flash;
+            let speaker = &mut session.as_mut().expect(NO_SESSION).speaker;
             let mut draw_flashes = gs.draw_flashes;
             let mut rotation_speed = gs.rotation_speed;
-            if is_key_pressed(KeyCode::Space) {
-                new_game(rng, gs.high_score)
+            if input.pressed(Action::Start) {
+                new_game(
+                    session.as_ref().expect(NO_SESSION),
+                    args,
+                    rng,
+                    gs.high_score,
+                )
+            } else if input.pressed(Action::Quit) {
+                let name = session.take().map(|session| session.name);
+                GameState {
+                    play_state: level_select_state(name.as_deref()),
+                    ..gs
+                }
+            } else if input.pressed(Action::Replay) {
+                let level = &session.as_ref().expect(NO_SESSION).level;
+                let play_state = replay_state(
+                    level,
+                    replay.clone(),
+                    PlayState::Death {
+                        demonstration,
+                        replay,
+                        killed_by,
+                    },
+                );
+                GameState {
+                    play_state,
+                    pattern: Pattern::empty(),
+                    tick: Tick::Countdown(0),
+                    ..gs
+                }
             } else {
-                if matches!(speaker.tick(8), Tick::Beat(_)) {
-                    draw_flashes = speaker.beat_fraction() < 0.5;
+                if matches!(
+                    speaker.tick(demonstration.countdown_length()),
+                    Tick::Beat(_)
+                ) {
+                    draw_flashes =
+                        reduced || demonstration.flash_progress(speaker.tick_fraction()) < 0.5;
                 }
-                if let Some(attack) = &attack {
-                    while let Some(tick) = speaker.process_tick(8) {
-                        match tick {
-                            Tick::Countdown(0..7) => {}
-                            Tick::Countdown(7) => draw_flashes = false,
-                            Tick::Countdown(_) => unreachable!(),
-                            Tick::Beat(_) => {
-                                if commands.is_empty() {
-                                    let new_transform = attack.transform.construct(rng, gs.player);
-                                    let transform = Transform {
-                                        origin: new_transform.origin,
-                                        transpose: original_transform.transpose,
-                                        mirror: original_transform.mirror,
-                                    };
-                                    attack.clone().enqueue(&mut commands, transform);
-                                }
-                                loop {
-                                    let Some(command) = commands.pop_front() else {
-                                        break;
-                                    };
-                                    match command {
-                                        Command::NewAttack(_, _) => {
-                                            rotation_speed = random_rotation_speed_slow(rng);
-                                        }
-                                        Command::FlashPattern(attack_pattern, attack_flash) => {
-                                            pattern = attack_pattern;
-                                            flash = attack_flash;
-                                            break;
-                                        }
-                                    }
-                                }
+                while speaker.process_tick() {
+                    let last_countdown = demonstration.countdown_length() - 1;
+                    for event in demonstration.advance() {
+                        match event {
+                            Event::Tick(Tick::Countdown(beat)) if beat == last_countdown => {
+                                draw_flashes = false;
                             }
+                            Event::NewAttack(_, _) => {
+                                rotation_speed = random_rotation_speed_slow(rng);
+                            }
+                            _ => {}
                         }
                     }
                 }
                 GameState {
+                    pattern: demonstration.pattern().clone(),
+                    flash: demonstration.flash(),
                     play_state: PlayState::Death {
-                        commands,
-                        attack,
-                        original_transform,
+                        demonstration,
+                        replay,
+                        killed_by,
                     },
                     rotation_speed,
-                    pattern,
-                    flash,
                     draw_flashes,
                     ..gs
                 }
             }
         }
-        PlayState::Transition(play_state) => {
-            speaker.restart_clock();
+        PlayState::Replay {
+            mut playback,
+            mut speed,
+            mut paused,
+            mut seek_entry,
+            previous,
+        } => {
+            let Session {
+                level,
+                speaker,
+                sounds,
+                ..
+            } = session.as_mut().expect(NO_SESSION);
+            let beat = |playback: &Playback<N, M>| {
+                playback.time() - Simulation::<N, M>::COUNTDOWN_LENGTH as f64
+            };
+            let resume_music = |speaker: &mut Speaker, playback: &Playback<N, M>, speed| {
+                if beat(playback) < 0.0 || playback.is_finished() {
+                    speaker.stop_music();
+                } else {
+                    let seed = playback.simulation().seed();
+                    speaker
+                        .play_music(&mut StdRng::seed_from_u64(seed))
+                        .unwrap();
+                    speaker.play_music_from(beat(playback), speed).unwrap();
+                }
+            };
+            if input.pressed(Action::Quit) {
+                speaker.stop_music();
+                speaker.restart_clock();
+                return GameState {
+                    play_state: *previous,
+                    ..gs
+                };
+            }
+            let mut tick = gs.tick;
+            let mut rotation_speed = gs.rotation_speed;
+            let mut music_changed = false;
+            if input.pressed(Action::Start) {
+                paused = !paused;
+                music_changed = true;
+            }
+            if is_key_pressed(KeyCode::Up) && speed < 4.0 {
+                speed *= 2.0;
+                music_changed = true;
+            }
+            if is_key_pressed(KeyCode::Down) && speed > 0.25 {
+                speed /= 2.0;
+                music_changed = true;
+            }
+            while let Some(c) = get_char_pressed() {
+                if c.is_ascii_digit() && seek_entry.len() < 6 {
+                    seek_entry.push(c);
+                }
+            }
+            let seek_beat = if is_key_pressed(KeyCode::Left) {
+                Some((beat(&playback) - 8.0).max(0.0) as u64)
+            } else if is_key_pressed(KeyCode::Right) {
+                Some((beat(&playback) + 8.0).max(0.0) as u64)
+            } else if is_key_pressed(KeyCode::Enter) {
+                seek_entry.parse().ok()
+            } else {
+                None
+            };
+            if let Some(seek_beat) = seek_beat {
+                seek_entry.clear();
+                playback.seek(seek_beat + Simulation::<N, M>::COUNTDOWN_LENGTH);
+                let ticks = playback.simulation().ticks();
+                tick = Tick::new(
+                    ticks.saturating_sub(1) / TICKS_PER_BEAT,
+                    Simulation::<N, M>::COUNTDOWN_LENGTH,
+                );
+                music_changed = true;
+            }
+            if !paused {
+                let frame_beats = get_frame_time() as f64 * speaker.beats_per_second() * speed;
+                for event in playback.advance(frame_beats) {
+                    match event {
+                        Event::Tick(next_tick) => {
+                            tick = next_tick;
+                            play_tick_sounds(
+                                speaker,
+                                sounds,
+                                tick,
+                                Simulation::<N, M>::COUNTDOWN_LENGTH,
+                            );
+                            music_changed |= tick == Tick::Beat(0);
+                        }
+                        Event::NewAttack(_, _) => {
+                            rotation_speed = random_rotation_speed_fast(rng);
+                        }
+                        Event::Flash(_, _) | Event::ChangeStyle(_) => {}
+                        Event::Death => {
+                            speaker.play_sound(&sounds.death).unwrap();
+                            music_changed = true;
+                        }
+                    }
+                }
+            }
+            if music_changed {
+                if paused {
+                    speaker.stop_music();
+                } else {
+                    resume_music(speaker, &playback, speed);
+                }
+            }
+            let simulation = playback.simulation();
+            speaker.set_bpm(simulation.style().bpm.unwrap_or(level.bpm));
             GameState {
+                player: simulation.player(),
+                pattern: simulation.pattern().clone(),
+                flash: simulation.flash(),
+                draw_flashes: reduced
+                    || playback.is_finished()
+                    || simulation.flash_progress((playback.time() * TICKS_PER_BEAT as f64).fract())
+                        < 0.5,
+                style: simulation.style().clone(),
+                tick,
+                rotation_speed,
+                play_state: PlayState::Replay {
+                    playback,
+                    speed,
+                    paused,
+                    seek_entry,
+                    previous,
+                },
+                ..gs
+            }
+        }
+        PlayState::Editor(mut editor) => {
+            let speaker = &mut session.as_mut().expect(NO_SESSION).speaker;
+            let screen = Screen::new(screen_width(), screen_height());
+            match editor.update(&screen, gs.rotation, speaker, reduced, rng) {
+                EditorResult::Open => GameState {
+                    play_state: PlayState::Editor(editor),
+                    ..gs
+                },
+                EditorResult::Closed => {
+                    // The level may have changed on disk, so it's loaded
+                    // again from level selection.
+                    speaker.restart_clock();
+                    let name = session.take().map(|session| session.name);
+                    GameState {
+                        play_state: level_select_state(name.as_deref()),
+                        rotation_speed: random_rotation_speed_slow(rng),
+                        ..gs
+                    }
+                }
+            }
+        }
+        PlayState::Settings { mut menu, previous } => match menu.update(settings) {
+            MenuResult::Open => GameState {
+                play_state: PlayState::Settings { menu, previous },
+                ..gs
+            },
+            MenuResult::Closed => GameState {
+                play_state: *previous,
+                ..gs
+            },
+        },
+        PlayState::Transition(play_state) => {
+            session.as_mut().expect(NO_SESSION).speaker.restart_clock();
+            let mut gs = GameState {
                 play_state: *play_state,
                 ..gs
+            };
+            if reduced {
+                gs.flash_limiter.start_fade();
             }
+            gs
         }
     };
     let frame_time = get_frame_time();
     let (in_rotation, out_rotation) = gs.rotation;
     let (in_rotation_speed, out_rotation_speed) = gs.rotation_speed;
+    let frame_time = frame_time * gs.style.rotation_speed;
     let rotation = (
         (in_rotation + in_rotation_speed * frame_time).rem_euclid(360.0),
         (out_rotation + out_rotation_speed * frame_time).rem_euclid(360.0),
     );
-    GameState { rotation, ..gs }
+    let mut gs = GameState { rotation, ..gs };
+    if let Some(session) = session
+        && reduced_flashing(settings, Some(session))
+    {
+        gs.flash_limiter.update(
+            &gs.pattern,
+            gs.flash,
+            gs.draw_flashes,
+            gs.style.colors.as_ref().unwrap_or(&session.level.colors),
+        );
+    }
+    gs
 }
 
-fn draw<const N: usize>(screen: &Screen<N>, game_state: &GameState<N>, level: &Level<N>) {
+fn draw<const N: usize, const M: usize>(
+    screen: &Screen<N, M>,
+    game_state: &GameState<N, M>,
+    session: Option<&Session<N, M>>,
+    save: &SaveData,
+    settings: &Settings,
+) {
     let GameState {
         play_state,
         player,
@@ -346,21 +1133,95 @@ fn draw<const N: usize>(screen: &Screen<N>, game_state: &GameState<N>, level: &L
         pattern,
         flash,
         draw_flashes,
+        flash_limiter,
+        style,
         tick,
         high_score,
     } = game_state;
+    let reduced = reduced_flashing(settings, session);
     clear_background(WHITE);
     match play_state {
-        PlayState::Transition(_) => screen.flash(),
-        _ => {
+        PlayState::LevelSelect { select, preview } => match (select.entry(), preview) {
+            (None, _) => {
+                screen.blank();
+                screen.draw_text("no levels", 0.0);
+            }
+            (Some(LevelEntry { name, id, .. }), Ok(level)) => {
+                screen.draw_playfield(
+                    Flashes::Blinking {
+                        pattern: &Pattern::empty(),
+                        flash: Flash::Warn,
+                        draw_flashes: false,
+                    },
+                    *player,
+                    *rotation,
+                    &level.colors,
+                );
+                screen.draw_text(name, 1.0 / 8.0);
+                screen.draw_text(&format!("{} BPM", level.bpm), -1.0 / 8.0);
+                let record = save.record(id);
+                screen.draw_caption(
+                    &format!(
+                        "high score {} - {} plays - {} deaths",
+                        record.high_score, record.plays, record.deaths
+                    ),
+                    -1.0 / 4.0,
+                );
+                if let Some((attack, deaths)) = record.deadliest_attack() {
+                    screen.draw_caption(
+                        &format!("deadliest attack: {attack} ({deaths} deaths)"),
+                        -5.0 / 16.0,
+                    );
+                }
+            }
+            (Some(LevelEntry { name, .. }), Err(e)) => {
+                screen.blank();
+                screen.draw_text(name, 1.0 / 8.0);
+                screen.draw_paragraph(e, -1.0 / 8.0);
+            }
+        },
+        PlayState::ChangeSides(_) => unreachable!("{SIDES_CHANGED}"),
+        PlayState::Settings { menu, .. } => menu.draw(screen, settings),
+        PlayState::Editor(editor) => {
+            editor.draw(screen, *rotation, &session.expect(NO_SESSION).level.colors);
+        }
+        PlayState::Transition(_) if !reduced => screen.flash(),
+        PlayState::PracticeSelect { selected, speed } => {
+            let level = &session.expect(NO_SESSION).level;
             screen.draw_playfield(
-                pattern,
-                *flash,
-                *draw_flashes,
+                Flashes::Blinking {
+                    pattern: &Pattern::empty(),
+                    flash: Flash::Warn,
+                    draw_flashes: false,
+                },
                 *player,
                 *rotation,
                 &level.colors,
             );
+            let attack = &level.attacks[*selected];
+            screen.draw_text(&attack.label(*selected), 1.0 / 8.0);
+            screen.draw_text(&format!("{speed}% speed"), -1.0 / 8.0);
+            screen.draw_caption(
+                &format!("practice at {} BPM", practice_bpm(level, *speed)),
+                -1.0 / 4.0,
+            );
+        }
+        _ => {
+            let level = &session.expect(NO_SESSION).level;
+            let flashes = if reduced {
+                Flashes::Limited(flash_limiter)
+            } else {
+                Flashes::Blinking {
+                    pattern,
+                    flash: *flash,
+                    draw_flashes: *draw_flashes,
+                }
+            };
+            let colors = style.colors.as_ref().unwrap_or(&level.colors);
+            screen.draw_playfield(flashes, *player, *rotation, colors);
+            if reduced {
+                screen.fade(flash_limiter);
+            }
             let tick_text = match tick {
                 Tick::Countdown(tick @ 0..3) => format!("({})", 3 - tick),
                 Tick::Countdown(3) => "GO".into(),
@@ -368,7 +1229,55 @@ fn draw<const N: usize>(screen: &Screen<N>, game_state: &GameState<N>, level: &L
                 Tick::Beat(beat) => beat.to_string(),
             };
             screen.draw_text(&tick_text, 1.0 / 8.0);
-            screen.draw_text(&high_score.to_string(), -1.0 / 8.0);
+            let score_text = match play_state {
+                PlayState::Practice { practice, .. } => format!("{} deaths", practice.deaths()),
+                _ => high_score.to_string(),
+            };
+            screen.draw_text(&score_text, -1.0 / 8.0);
+            match play_state {
+                PlayState::Death {
+                    replay, killed_by, ..
+                } => {
+                    screen.draw_caption(&format!("seed {}", replay.seed), -1.0 / 4.0);
+                    if let Some(name) = killed_by.as_deref().filter(|name| !name.is_empty()) {
+                        screen.draw_caption(&format!("killed by {name}"), -5.0 / 16.0);
+                    }
+                }
+                PlayState::Practice {
+                    selected, speed, ..
+                } => {
+                    screen.draw_caption(
+                        &format!(
+                            "practice {} - {speed}%",
+                            level.attacks[*selected].label(*selected)
+                        ),
+                        -1.0 / 4.0,
+                    );
+                }
+                PlayState::Replay {
+                    playback,
+                    speed,
+                    paused,
+                    seek_entry,
+                    ..
+                } => {
+                    let mut status = if *paused {
+                        "paused".into()
+                    } else {
+                        format!("{speed}x")
+                    };
+                    if let Some(attack) = playback.simulation().attack()
+                        && !attack.name.is_empty()
+                    {
+                        status = format!("{status} - {}", attack.name);
+                    }
+                    screen.draw_caption(&format!("replay - {status}"), -1.0 / 4.0);
+                    if !seek_entry.is_empty() {
+                        screen.draw_caption(&format!("seek to beat {seek_entry}"), -5.0 / 16.0);
+                    }
+                }
+                _ => {}
+            }
         }
     }
 }
@@ -0,0 +1,315 @@
+use std::{fmt, marker::PhantomData, ops::Range};
+
+use serde::{
+    Deserializer, Serializer,
+    de::{self, SeqAccess, Visitor, value::SeqAccessDeserializer},
+};
+use serde_with::{BoolFromInt, DeserializeAs, SerializeAs};
+
+pub const GENERATORS: &str =
+    r#""all", "none", "checker", "every:K", "ring-inner:K" or "ring-outer:K""#;
+
+// A row of tiles around the inner ring, written as 0/1 numbers, as a string
+// of `#` and `.` such as "#..#..", or as a hex mask such as "0x9", where bit
+// k is inner position k.
+pub fn parse_row<const N: usize>(s: &str) -> Result<[bool; N], String> {
+    if let Some(hex) = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        // `from_str_radix` also accepts a sign in front of the digits.
+        let mask = Some(hex)
+            .filter(|hex| hex.bytes().all(|b| b.is_ascii_hexdigit()))
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| format!("invalid hex mask {s:?}"))?;
+        if mask >> N != 0 {
+            return Err(format!(
+                "hex mask {s:?} has bits past inner position {}",
+                N - 1
+            ));
+        }
+        return Ok(std::array::from_fn(|i| mask >> i & 1 == 1));
+    }
+    let tiles = s
+        .chars()
+        .map(|c| match c {
+            '#' => Ok(true),
+            '.' => Ok(false),
+            c => Err(format!("invalid tile {c:?} in {s:?}, expected '#' or '.'")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let count = tiles.len();
+    tiles
+        .try_into()
+        .map_err(|_| format!("expected {N} tiles in {s:?}, found {count}"))
+}
+
+fn generator_argument(name: &str, argument: &str, range: Range<usize>) -> Result<usize, String> {
+    match argument.parse() {
+        Ok(k) if range.contains(&k) => Ok(k),
+        _ => Err(format!(
+            "{name} needs a number from {} to {}, got {argument:?}",
+            range.start,
+            range.end - 1
+        )),
+    }
+}
+
+// A whole pattern described by name instead of tile by tile.
+pub fn generate<const N: usize, const M: usize>(s: &str) -> Result<[[bool; N]; M], String> {
+    let (name, argument) = match s.split_once(':') {
+        Some((name, argument)) => (name, Some(argument)),
+        None => (s, None),
+    };
+    let strikes: Box<dyn Fn(usize, usize) -> bool> = match (name, argument) {
+        ("all", None) => Box::new(|_, _| true),
+        ("none", None) => Box::new(|_, _| false),
+        ("checker", None) => Box::new(|i, o| (i + o) % 2 == 0),
+        ("every", Some(k)) => {
+            let k = generator_argument(name, k, 1..N + 1)?;
+            Box::new(move |i, _| i.is_multiple_of(k))
+        }
+        ("ring-inner", Some(k)) => {
+            let k = generator_argument(name, k, 0..N)?;
+            Box::new(move |i, _| i == k)
+        }
+        ("ring-outer", Some(k)) => {
+            let k = generator_argument(name, k, 0..M)?;
+            Box::new(move |_, o| o == k)
+        }
+        _ => return Err(format!("unknown pattern {s:?}, expected {GENERATORS}")),
+    };
+    Ok(std::array::from_fn(|o| {
+        std::array::from_fn(|i| strikes(i, o))
+    }))
+}
+
+// serde_with adapter for a row in any of the notations. Rows are always
+// written back as 0/1 numbers.
+pub struct Row;
+
+impl<'de, const N: usize> DeserializeAs<'de, [bool; N]> for Row {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<[bool; N], D::Error> {
+        struct RowVisitor<const N: usize>;
+
+        impl<'de, const N: usize> Visitor<'de> for RowVisitor<N> {
+            type Value = [bool; N];
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(
+                    f,
+                    "an array of {N} tiles, a string such as \"#..#..\" or a hex mask"
+                )
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                parse_row(s).map_err(E::custom)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                <[BoolFromInt; N]>::deserialize_as(SeqAccessDeserializer::new(seq))
+            }
+        }
+
+        deserializer.deserialize_any(RowVisitor)
+    }
+}
+
+impl<const N: usize> SerializeAs<[bool; N]> for Row {
+    fn serialize_as<S: Serializer>(source: &[bool; N], serializer: S) -> Result<S::Ok, S::Error> {
+        <[BoolFromInt; N]>::serialize_as(source, serializer)
+    }
+}
+
+// serde_with adapter for whole patterns, which can also be a generator
+// name instead of a list of rows. In the `beats` layouts, a generator gives
+// every pattern of the attack.
+pub struct Tiles;
+
+pub trait Listed<'de>: Sized {
+    fn generated(s: &str) -> Result<Self, String>;
+    fn listed<A: SeqAccess<'de>>(seq: A) -> Result<Self, A::Error>;
+}
+
+impl<'de, const N: usize, const M: usize> Listed<'de> for [[bool; N]; M] {
+    fn generated(s: &str) -> Result<Self, String> {
+        generate(s)
+    }
+
+    fn listed<A: SeqAccess<'de>>(seq: A) -> Result<Self, A::Error> {
+        <[Row; M]>::deserialize_as(SeqAccessDeserializer::new(seq))
+    }
+}
+
+impl<'de, const N: usize, const M: usize, const P: usize> Listed<'de> for [[[bool; N]; P]; M] {
+    fn generated(s: &str) -> Result<Self, String> {
+        Ok(generate::<N, M>(s)?.map(|row| [row; P]))
+    }
+
+    fn listed<A: SeqAccess<'de>>(seq: A) -> Result<Self, A::Error> {
+        <[[Row; P]; M]>::deserialize_as(SeqAccessDeserializer::new(seq))
+    }
+}
+
+impl<'de, T: Listed<'de>> DeserializeAs<'de, T> for Tiles {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<T, D::Error> {
+        struct TilesVisitor<T>(PhantomData<T>);
+
+        impl<'de, T: Listed<'de>> Visitor<'de> for TilesVisitor<T> {
+            type Value = T;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "an array of rows or one of {GENERATORS}")
+            }
+
+            fn visit_str<E: de::Error>(self, s: &str) -> Result<Self::Value, E> {
+                T::generated(s).map_err(E::custom)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, seq: A) -> Result<Self::Value, A::Error> {
+                T::listed(seq)
+            }
+        }
+
+        deserializer.deserialize_any(TilesVisitor(PhantomData))
+    }
+}
+
+impl<const N: usize, const M: usize> SerializeAs<[[bool; N]; M]> for Tiles {
+    fn serialize_as<S: Serializer>(
+        source: &[[bool; N]; M],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        <[Row; M]>::serialize_as(source, serializer)
+    }
+}
+
+impl<const N: usize, const M: usize, const P: usize> SerializeAs<[[[bool; N]; P]; M]> for Tiles {
+    fn serialize_as<S: Serializer>(
+        source: &[[[bool; N]; P]; M],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        <[[Row; P]; M]>::serialize_as(source, serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use serde_with::serde_as;
+
+    use super::*;
+
+    #[test]
+    fn rows_can_be_strings_or_hex_masks() {
+        let row = [true, false, false, true, false, false];
+        assert_eq!(parse_row::<6>("#..#.."), Ok(row));
+        assert_eq!(parse_row::<6>("0x9"), Ok(row));
+        assert_eq!(parse_row::<6>("0X09"), Ok(row));
+        assert_eq!(parse_row::<3>("0x0"), Ok([false; 3]));
+        assert_eq!(parse_row::<3>("0x7"), Ok([true; 3]));
+    }
+
+    #[test]
+    fn bad_rows_are_explained() {
+        assert_eq!(
+            parse_row::<6>("#..#."),
+            Err("expected 6 tiles in \"#..#.\", found 5".to_owned())
+        );
+        assert_eq!(
+            parse_row::<3>("#x#"),
+            Err("invalid tile 'x' in \"#x#\", expected '#' or '.'".to_owned())
+        );
+        assert_eq!(
+            parse_row::<3>("0x8"),
+            Err("hex mask \"0x8\" has bits past inner position 2".to_owned())
+        );
+        assert_eq!(
+            parse_row::<3>("0xg"),
+            Err("invalid hex mask \"0xg\"".to_owned())
+        );
+        assert_eq!(
+            parse_row::<4>("0x+9"),
+            Err("invalid hex mask \"0x+9\"".to_owned())
+        );
+    }
+
+    #[test]
+    fn generators_fill_whole_patterns() {
+        assert_eq!(generate::<3, 2>("all"), Ok([[true; 3]; 2]));
+        assert_eq!(generate::<3, 2>("none"), Ok([[false; 3]; 2]));
+        assert_eq!(
+            generate::<3, 2>("checker"),
+            Ok([[true, false, true], [false, true, false]])
+        );
+        assert_eq!(
+            generate::<6, 1>("every:3"),
+            Ok([[true, false, false, true, false, false]])
+        );
+        assert_eq!(generate::<3, 1>("every:3"), Ok([[true, false, false]]));
+        assert_eq!(
+            generate::<3, 2>("ring-inner:2"),
+            Ok([[false, false, true], [false, false, true]])
+        );
+        assert_eq!(
+            generate::<3, 2>("ring-outer:1"),
+            Ok([[false; 3], [true; 3]])
+        );
+    }
+
+    #[test]
+    fn generator_arguments_are_checked() {
+        assert_eq!(
+            generate::<3, 2>("every:0"),
+            Err("every needs a number from 1 to 3, got \"0\"".to_owned())
+        );
+        assert_eq!(
+            generate::<3, 2>("ring-inner:3"),
+            Err("ring-inner needs a number from 0 to 2, got \"3\"".to_owned())
+        );
+        assert_eq!(
+            generate::<3, 2>("ring-outer:x"),
+            Err("ring-outer needs a number from 0 to 1, got \"x\"".to_owned())
+        );
+        for name in ["stripes", "all:1", "every"] {
+            assert!(
+                generate::<3, 2>(name)
+                    .unwrap_err()
+                    .starts_with(&format!("unknown pattern \"{name}\""))
+            );
+        }
+    }
+
+    #[serde_as]
+    #[derive(Debug, PartialEq, Deserialize, Serialize)]
+    struct Shapes {
+        #[serde_as(as = "Tiles")]
+        pattern: [[bool; 3]; 2],
+        #[serde_as(as = "Tiles")]
+        patterns: [[[bool; 3]; 2]; 2],
+    }
+
+    #[test]
+    fn tiles_mix_notations_and_write_numbers() {
+        let shapes: Shapes = serde_json::from_value(json!({
+            "pattern": ["#.#", [0, 1, 0]],
+            "patterns": "ring-inner:1",
+        }))
+        .unwrap();
+        assert_eq!(
+            shapes,
+            Shapes {
+                pattern: [[true, false, true], [false, true, false]],
+                patterns: [[[false, true, false]; 2]; 2],
+            }
+        );
+        let written = serde_json::to_value(&shapes).unwrap();
+        assert_eq!(written["pattern"], json!([[1, 0, 1], [0, 1, 0]]));
+        assert_eq!(serde_json::from_value::<Shapes>(written).unwrap(), shapes);
+
+        let error = serde_json::from_value::<Shapes>(json!({
+            "pattern": ["#.#", "0x9"],
+            "patterns": "all",
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("has bits past inner position 2"));
+    }
+}
@@ -1,4 +1,4 @@
-use std::ops::{Add, Index, Neg, Sub};
+use std::ops::{Add, Index, IndexMut, Neg, Sub};
 
 use rand::distr::{Distribution, StandardUniform};
 
@@ -7,21 +7,23 @@ pub struct Coord<const N: usize> {
     inner: usize,
 }
 
-#[derive(Clone, Debug)]
-pub struct PatternBlueprint<const N: usize> {
-    inner: [[bool; N]; N],
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PatternBlueprint<const N: usize, const M: usize> {
+    inner: [[bool; N]; M],
 }
 
-#[derive(Clone, Debug)]
-pub struct Pattern<const N: usize> {
-    inner: [[bool; N]; N],
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pattern<const N: usize, const M: usize> {
+    inner: [[bool; N]; M],
 }
 
-#[derive(Clone, Copy, Debug, Default)]
-pub struct Transform<const N: usize> {
-    pub origin: (Coord<N>, Coord<N>),
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Transform<const N: usize, const M: usize> {
+    pub origin: (Coord<N>, Coord<M>),
     pub transpose: bool,
-    pub mirror: bool,
+    pub mirror_inner: bool,
+    pub mirror_outer: bool,
+    pub reverse: bool,
 }
 
 impl<const N: usize> Coord<N> {
@@ -36,6 +38,10 @@ impl<const N: usize> Coord<N> {
         self.inner
     }
 
+    pub fn convert<const K: usize>(self) -> Coord<K> {
+        Coord::new(self.inner)
+    }
+
     pub fn iter_all() -> impl Iterator<Item = Self> {
         (0..N).map(Self::new)
     }
@@ -71,58 +77,229 @@ impl<const N: usize> Sub for Coord<N> {
     }
 }
 
-impl<const N: usize> PatternBlueprint<N> {
-    pub fn new(inner: [[bool; N]; N]) -> Self {
+impl<const N: usize, const M: usize> PatternBlueprint<N, M> {
+    pub fn new(inner: [[bool; N]; M]) -> Self {
         Self { inner }
     }
 
-    pub fn construct(&self, transform: Transform<N>) -> Pattern<N> {
+    pub fn empty() -> Self {
+        Self {
+            inner: [[false; N]; M],
+        }
+    }
+
+    pub fn rows(&self) -> &[[bool; N]; M] {
+        &self.inner
+    }
+
+    pub fn construct(&self, transform: Transform<N, M>) -> Pattern<N, M> {
         let Transform {
             origin,
             transpose,
-            mirror,
+            mirror_inner,
+            mirror_outer,
+            reverse: _,
         } = transform;
-        let mut inner = [[false; N]; N];
+        let mut inner = [[false; N]; M];
         for o in Coord::iter_all() {
             for i in Coord::iter_all() {
                 let (origin_i, origin_o) = origin;
-                inner[(origin_o + o).inner()][(origin_i + i).inner()] =
-                    self[match (transpose, mirror) {
-                        (false, false) => (i, o),
-                        (true, false) => (o, i),
-                        (false, true) => (-i, -o),
-                        (true, true) => (-o, -i),
-                    }];
+                let source_i = if mirror_inner { -i } else { i };
+                let source_o = if mirror_outer { -o } else { o };
+                inner[(origin_o + o).inner()][(origin_i + i).inner()] = self[if transpose {
+                    (source_o.convert(), source_i.convert())
+                } else {
+                    (source_i, source_o)
+                }];
             }
         }
         Pattern { inner }
     }
 }
 
-impl<const N: usize> Index<(Coord<N>, Coord<N>)> for PatternBlueprint<N> {
+impl<const N: usize, const M: usize> Index<(Coord<N>, Coord<M>)> for PatternBlueprint<N, M> {
     type Output = bool;
 
-    fn index(&self, (i, o): (Coord<N>, Coord<N>)) -> &Self::Output {
+    fn index(&self, (i, o): (Coord<N>, Coord<M>)) -> &Self::Output {
         &self.inner[o.inner()][i.inner()]
     }
 }
 
-impl<const N: usize> Pattern<N> {
-    pub fn new(inner: [[bool; N]; N]) -> Self {
+impl<const N: usize, const M: usize> IndexMut<(Coord<N>, Coord<M>)> for PatternBlueprint<N, M> {
+    fn index_mut(&mut self, (i, o): (Coord<N>, Coord<M>)) -> &mut Self::Output {
+        &mut self.inner[o.inner()][i.inner()]
+    }
+}
+
+impl<const N: usize, const M: usize> Pattern<N, M> {
+    pub fn new(inner: [[bool; N]; M]) -> Self {
         Self { inner }
     }
 
     pub fn empty() -> Self {
         Self {
-            inner: [[false; N]; N],
+            inner: [[false; N]; M],
         }
     }
 }
 
-impl<const N: usize> Index<(Coord<N>, Coord<N>)> for Pattern<N> {
+impl<const N: usize, const M: usize> Index<(Coord<N>, Coord<M>)> for Pattern<N, M> {
     type Output = bool;
 
-    fn index(&self, (i, o): (Coord<N>, Coord<N>)) -> &Self::Output {
+    fn index(&self, (i, o): (Coord<N>, Coord<M>)) -> &Self::Output {
         &self.inner[o.inner()][i.inner()]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    use super::*;
+    use crate::level::TransformBlueprint;
+
+    fn random_blueprints<const N: usize, const M: usize>() -> Vec<PatternBlueprint<N, M>> {
+        let mut rng = StdRng::seed_from_u64(15);
+        (0..16)
+            .map(|_| PatternBlueprint::new(std::array::from_fn(|_| rng.random())))
+            .collect()
+    }
+
+    // Constructs the pattern, then reads it back as a blueprint so that
+    // transforms can be applied one after another.
+    fn apply<const N: usize, const M: usize>(
+        blueprint: &PatternBlueprint<N, M>,
+        transform: Transform<N, M>,
+    ) -> PatternBlueprint<N, M> {
+        PatternBlueprint::new(blueprint.construct(transform).inner)
+    }
+
+    fn mirrors<const N: usize, const M: usize>(inner: bool, outer: bool) -> Transform<N, M> {
+        Transform {
+            mirror_inner: inner,
+            mirror_outer: outer,
+            ..Default::default()
+        }
+    }
+
+    fn shift<const N: usize, const M: usize>(i: usize, o: usize) -> Transform<N, M> {
+        Transform {
+            origin: (Coord::new(i), Coord::new(o)),
+            ..Default::default()
+        }
+    }
+
+    fn check_laws<const N: usize, const M: usize>() {
+        let inner = mirrors::<N, M>(true, false);
+        let outer = mirrors::<N, M>(false, true);
+        for blueprint in random_blueprints::<N, M>() {
+            assert_eq!(apply(&blueprint, Transform::default()), blueprint);
+            assert_eq!(apply(&apply(&blueprint, inner), inner), blueprint);
+            assert_eq!(apply(&apply(&blueprint, outer), outer), blueprint);
+            assert_eq!(
+                apply(&apply(&blueprint, inner), outer),
+                apply(&apply(&blueprint, outer), inner)
+            );
+            assert_eq!(
+                apply(&blueprint, mirrors(true, true)),
+                apply(&apply(&blueprint, inner), outer)
+            );
+            for (a, b) in [((1, 2), (3, 4)), ((0, 5), (7, 1)), ((2, 2), (N - 2, M - 2))] {
+                assert_eq!(
+                    apply(&apply(&blueprint, shift(a.0, a.1)), shift(b.0, b.1)),
+                    apply(&blueprint, shift(a.0 + b.0, a.1 + b.1))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn transforms_follow_the_group_laws() {
+        check_laws::<3, 3>();
+        check_laws::<6, 6>();
+        check_laws::<8, 8>();
+        check_laws::<4, 3>();
+    }
+
+    // Mirroring happens around the origin, so a mirrored transform is the
+    // mirror followed by the shift, and mirroring undoes a shift in the
+    // mirrored directions.
+    fn check_conjugation<const N: usize, const M: usize>() {
+        for blueprint in random_blueprints::<N, M>() {
+            for (inner, outer) in [(true, false), (false, true), (true, true)] {
+                let mirror = mirrors::<N, M>(inner, outer);
+                for (i, o) in [(1, 2), (N - 1, 0), (2, M - 1)] {
+                    let mirrored = Transform {
+                        origin: (Coord::new(i), Coord::new(o)),
+                        ..mirror
+                    };
+                    assert_eq!(
+                        apply(&blueprint, mirrored),
+                        apply(&apply(&blueprint, mirror), shift(i, o))
+                    );
+                    let (back_i, back_o) =
+                        (if inner { N - i } else { i }, if outer { M - o } else { o });
+                    assert_eq!(
+                        apply(&apply(&apply(&blueprint, mirror), shift(i, o)), mirror),
+                        apply(&blueprint, shift(back_i, back_o))
+                    );
+                }
+            }
+        }
+        let mut tile = PatternBlueprint::<N, M>::empty();
+        tile[(Coord::ZERO, Coord::ZERO)] = true;
+        let mirrored = Transform {
+            origin: (Coord::new(1), Coord::new(2)),
+            ..mirrors(true, true)
+        };
+        assert_eq!(apply(&tile, mirrored), apply(&tile, shift::<N, M>(1, 2)));
+    }
+
+    #[test]
+    fn mirrors_conjugate_shifted_origins() {
+        check_conjugation::<3, 3>();
+        check_conjugation::<6, 6>();
+        check_conjugation::<8, 8>();
+        check_conjugation::<4, 3>();
+    }
+
+    #[test]
+    fn transpose_twice_is_identity() {
+        let transpose = Transform::<6, 6> {
+            transpose: true,
+            ..Default::default()
+        };
+        for blueprint in random_blueprints::<6, 6>() {
+            assert_eq!(apply(&apply(&blueprint, transpose), transpose), blueprint);
+        }
+    }
+
+    #[test]
+    fn variants_cover_every_constructed_transform() {
+        let mut rng = StdRng::seed_from_u64(15);
+        for flags in 0..32 {
+            let blueprint = TransformBlueprint {
+                transpose: flags & 1 != 0,
+                mirror: flags & 2 != 0,
+                mirror_inner: flags & 4 != 0,
+                mirror_outer: flags & 8 != 0,
+                reverse: flags & 16 != 0,
+                ..Default::default()
+            };
+            let variants = blueprint.variants::<6, 6>();
+            for (k, variant) in variants.iter().enumerate() {
+                assert!(!variants[..k].contains(variant));
+            }
+            let mut seen = vec![false; variants.len()];
+            for _ in 0..256 {
+                let transform = Transform {
+                    origin: (Coord::ZERO, Coord::ZERO),
+                    ..blueprint.construct::<6, 6>(&mut rng, (Coord::ZERO, Coord::ZERO))
+                };
+                let k = variants.iter().position(|&v| v == transform).unwrap();
+                seen[k] = true;
+            }
+            assert!(seen.iter().all(|&seen| seen), "{blueprint:?}");
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::{error::Error, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::level::Attack;
+use crate::script::Cues;
+use crate::simulation::{Event, Move, Simulation, TICKS_PER_BEAT};
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(from = "(u64, f64, Move)", into = "(u64, f64, Move)")]
+pub struct ReplayInput {
+    pub tick: u64,
+    pub time: f64,
+    pub movement: Move,
+}
+
+impl From<(u64, f64, Move)> for ReplayInput {
+    fn from((tick, time, movement): (u64, f64, Move)) -> Self {
+        Self {
+            tick,
+            time,
+            movement,
+        }
+    }
+}
+
+impl From<ReplayInput> for (u64, f64, Move) {
+    fn from(value: ReplayInput) -> Self {
+        (value.tick, value.time, value.movement)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Replay {
+    pub level: String,
+    pub seed: u64,
+    pub length: u64,
+    pub inputs: Vec<ReplayInput>,
+    #[serde(default)]
+    pub attacks: Vec<(u64, String)>,
+}
+
+impl Replay {
+    pub fn new(level: String, seed: u64) -> Self {
+        Self {
+            level,
+            seed,
+            length: 0,
+            inputs: Vec::new(),
+            attacks: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, tick: u64, time: f64, movement: Move) {
+        self.inputs.push(ReplayInput {
+            tick,
+            time,
+            movement,
+        });
+    }
+
+    pub fn record_attack(&mut self, tick: u64, name: &str) {
+        self.attacks.push((tick, name.to_owned()));
+    }
+
+    pub fn finish(&mut self, length: u64) {
+        self.length = length;
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn store(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+}
+
+pub struct Playback<const N: usize, const M: usize> {
+    attacks: Vec<Attack<N, M>>,
+    cues: Cues<N, M>,
+    replay: Replay,
+    simulation: Simulation<N, M>,
+    next_input: usize,
+    time: f64,
+}
+
+impl<const N: usize, const M: usize> Playback<N, M> {
+    pub fn new(attacks: Vec<Attack<N, M>>, cues: Cues<N, M>, replay: Replay) -> Self {
+        let simulation = Simulation::new(attacks.clone(), cues.clone(), replay.seed);
+        Self {
+            attacks,
+            cues,
+            replay,
+            simulation,
+            next_input: 0,
+            time: 0.0,
+        }
+    }
+
+    pub fn simulation(&self) -> &Simulation<N, M> {
+        &self.simulation
+    }
+
+    pub fn time(&self) -> f64 {
+        self.time
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.simulation.is_dead() || self.simulation.ticks() >= self.replay.length
+    }
+
+    fn apply_inputs(&mut self, until: f64) {
+        while let Some(input) = self.replay.inputs.get(self.next_input)
+            && input.tick <= self.simulation.ticks()
+            && input.time <= until
+        {
+            self.simulation.move_player(input.movement);
+            self.next_input += 1;
+        }
+    }
+
+    pub fn advance(&mut self, beats: f64) -> Vec<Event<N, M>> {
+        self.time += beats;
+        let mut events = Vec::new();
+        loop {
+            self.apply_inputs(self.time);
+            if self.is_finished()
+                || self.simulation.ticks() as f64 > self.time * TICKS_PER_BEAT as f64
+            {
+                break;
+            }
+            self.apply_inputs(f64::INFINITY);
+            events.extend(self.simulation.advance());
+        }
+        events
+    }
+
+    pub fn seek(&mut self, beat: u64) {
+        self.simulation =
+            Simulation::new(self.attacks.clone(), self.cues.clone(), self.replay.seed);
+        self.next_input = 0;
+        self.time = 0.0;
+        self.advance(beat as f64);
+    }
+}
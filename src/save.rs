@@ -0,0 +1,97 @@
+use std::{
+    collections::BTreeMap,
+    env,
+    error::Error,
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use in_ring_out_ring::replay::Replay;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+const SAVE_FILE: &str = "save.json";
+const REPLAYS_DIR: &str = "replays";
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LevelRecord {
+    pub high_score: u64,
+    pub music_record: i32,
+    pub plays: u64,
+    pub deaths: u64,
+    pub attack_deaths: BTreeMap<String, u64>,
+}
+
+impl LevelRecord {
+    pub fn deadliest_attack(&self) -> Option<(&str, u64)> {
+        self.attack_deaths
+            .iter()
+            .max_by_key(|&(_, &deaths)| deaths)
+            .map(|(name, &deaths)| (name.as_str(), deaths))
+    }
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct SaveData {
+    levels: BTreeMap<String, LevelRecord>,
+}
+
+pub fn level_id(name: &str, content: &str) -> String {
+    // FNV-1a, so that ids stay stable across builds and platforms.
+    let hash = content
+        .bytes()
+        .fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+    format!("{name}#{hash:016x}")
+}
+
+fn data_path(file_name: &str) -> PathBuf {
+    env::current_exe()
+        .map(|exe| exe.with_file_name(file_name))
+        .unwrap_or_else(|_| file_name.into())
+}
+
+pub fn load_data<T: DeserializeOwned + Default>(file_name: &str) -> T {
+    fs::read_to_string(data_path(file_name))
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub fn store_data<T: Serialize>(file_name: &str, data: &T) -> Result<(), Box<dyn Error>> {
+    let path = data_path(file_name);
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_string_pretty(data)?)?;
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+pub fn store_replay(replay: &Replay, level_name: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let dir = data_path(REPLAYS_DIR);
+    fs::create_dir_all(&dir)?;
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let path = dir.join(format!("{level_name}-{timestamp}.json"));
+    replay.store(&path)?;
+    Ok(path)
+}
+
+impl SaveData {
+    pub fn load() -> Self {
+        load_data(SAVE_FILE)
+    }
+
+    pub fn store(&self) -> Result<(), Box<dyn Error>> {
+        store_data(SAVE_FILE, self)
+    }
+
+    pub fn record(&self, id: &str) -> LevelRecord {
+        self.levels.get(id).cloned().unwrap_or_default()
+    }
+
+    pub fn record_mut(&mut self, id: &str) -> &mut LevelRecord {
+        self.levels.entry(id.to_owned()).or_default()
+    }
+}
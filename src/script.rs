@@ -0,0 +1,175 @@
+use serde::{Deserialize, Serialize};
+
+use crate::level::{Attack, LevelColors, TransformBlueprint};
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct Script<const N: usize, const M: usize> {
+    #[serde(default = "default_beats_per_bar")]
+    pub beats_per_bar: u64,
+    pub events: Vec<ScriptEvent<N, M>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub length: Option<u64>,
+    #[serde(default = "default_repeat")]
+    pub repeat: u64,
+}
+
+fn default_beats_per_bar() -> u64 {
+    4
+}
+
+fn default_repeat() -> u64 {
+    1
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+pub struct ScriptEvent<const N: usize, const M: usize> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub beat: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bar: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attack: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transform: Option<TransformBlueprint>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bpm: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colors: Option<LevelColors<N, M>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotation_speed: Option<f32>,
+}
+
+#[derive(Clone, Debug)]
+pub enum StyleChange<const N: usize, const M: usize> {
+    Bpm(f64),
+    Colors(LevelColors<N, M>),
+    RotationSpeed(f32),
+}
+
+#[derive(Clone, Debug)]
+pub struct Style<const N: usize, const M: usize> {
+    pub bpm: Option<f64>,
+    pub colors: Option<LevelColors<N, M>>,
+    pub rotation_speed: f32,
+}
+
+impl<const N: usize, const M: usize> Default for Style<N, M> {
+    fn default() -> Self {
+        Self {
+            bpm: None,
+            colors: None,
+            rotation_speed: 1.0,
+        }
+    }
+}
+
+impl<const N: usize, const M: usize> Style<N, M> {
+    pub fn apply(&mut self, change: StyleChange<N, M>) {
+        match change {
+            StyleChange::Bpm(bpm) => self.bpm = Some(bpm),
+            StyleChange::Colors(colors) => self.colors = Some(colors),
+            StyleChange::RotationSpeed(rotation_speed) => self.rotation_speed = rotation_speed,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Cue<const N: usize, const M: usize> {
+    Attack(Attack<N, M>),
+    Style(StyleChange<N, M>),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Cues<const N: usize, const M: usize> {
+    pub cues: Vec<(u64, Cue<N, M>)>,
+    pub length: u64,
+    pub repeat: u64,
+}
+
+impl<const N: usize, const M: usize> Cues<N, M> {
+    pub fn end(&self) -> u64 {
+        self.length * self.repeat
+    }
+
+    pub fn at(&self, beat: u64) -> impl Iterator<Item = &Cue<N, M>> {
+        let beat = (beat < self.end()).then(|| beat % self.length);
+        self.cues
+            .iter()
+            .filter(move |(b, _)| Some(*b) == beat)
+            .map(|(_, cue)| cue)
+    }
+}
+
+impl<const N: usize, const M: usize> ScriptEvent<N, M> {
+    pub fn beat(&self, beats_per_bar: u64) -> Result<u64, String> {
+        match (self.beat, self.bar) {
+            (Some(beat), None) => Ok(beat),
+            (None, Some(bar)) => Ok(bar * beats_per_bar),
+            (Some(_), Some(_)) => Err("an event can't set both beat and bar".to_owned()),
+            (None, None) => Err("an event needs a beat or a bar".to_owned()),
+        }
+    }
+}
+
+impl<const N: usize, const M: usize> Script<N, M> {
+    pub fn cues(&self, attacks: &[Attack<N, M>]) -> Result<Cues<N, M>, String> {
+        if self.beats_per_bar == 0 {
+            return Err("beats_per_bar must be positive".to_owned());
+        }
+        if self.repeat == 0 {
+            return Err("repeat must be positive".to_owned());
+        }
+        let mut cues = Vec::new();
+        let mut end = 0;
+        for (k, event) in self.events.iter().enumerate() {
+            let beat = event
+                .beat(self.beats_per_bar)
+                .map_err(|e| format!("event {k}: {e}"))?;
+            let count = cues.len();
+            if let Some(name) = &event.attack {
+                let Some(attack) = attacks.iter().find(|attack| attack.name == *name) else {
+                    return Err(format!("event {k}: there is no attack named \"{name}\""));
+                };
+                let mut attack = attack.clone();
+                if let Some(transform) = &event.transform {
+                    attack.transform = transform.clone();
+                }
+                end = end.max(beat + attack.beat_length());
+                cues.push((beat, Cue::Attack(attack)));
+            } else if event.transform.is_some() {
+                return Err(format!("event {k}: only attacks can have a transform"));
+            }
+            if let Some(bpm) = event.bpm {
+                if bpm <= 0.0 {
+                    return Err(format!("event {k}: bpm must be positive, got {bpm}"));
+                }
+                cues.push((beat, Cue::Style(StyleChange::Bpm(bpm))));
+            }
+            if let Some(colors) = &event.colors {
+                cues.push((beat, Cue::Style(StyleChange::Colors(colors.clone()))));
+            }
+            if let Some(rotation_speed) = event.rotation_speed {
+                cues.push((beat, Cue::Style(StyleChange::RotationSpeed(rotation_speed))));
+            }
+            if cues.len() == count {
+                return Err(format!("event {k} doesn't do anything"));
+            }
+            end = end.max(beat + 1);
+        }
+        let length = self.length.unwrap_or(end);
+        if let Some((beat, _)) = cues.iter().find(|(beat, _)| *beat >= length) {
+            return Err(format!(
+                "an event at beat {beat} is past the end of the script at beat {length}"
+            ));
+        }
+        if length == 0 {
+            return Err("the script is empty".to_owned());
+        }
+        cues.sort_by_key(|(beat, _)| *beat);
+        Ok(Cues {
+            cues,
+            length,
+            repeat: self.repeat,
+        })
+    }
+}
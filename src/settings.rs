@@ -0,0 +1,165 @@
+use std::error::Error;
+
+use macroquad::input::{KeyCode, get_last_key_pressed, is_key_pressed};
+use serde::{Deserialize, Serialize};
+
+use crate::drawing::Screen;
+use crate::input::{Action, GamepadSettings, Key, KeyBindings};
+use crate::save;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub bindings: KeyBindings,
+    pub gamepad: GamepadSettings,
+    pub reduced_flashing: bool,
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        save::load_data(SETTINGS_FILE)
+    }
+
+    pub fn store(&self) -> Result<(), Box<dyn Error>> {
+        save::store_data(SETTINGS_FILE, self)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Row {
+    Binding(Action),
+    ResetBindings,
+    DeadZone,
+    ReducedFlashing,
+}
+
+const ROWS: [Row; 11] = [
+    Row::Binding(Action::InnerCcw),
+    Row::Binding(Action::InnerCw),
+    Row::Binding(Action::OuterCcw),
+    Row::Binding(Action::OuterCw),
+    Row::Binding(Action::Start),
+    Row::Binding(Action::Quit),
+    Row::Binding(Action::Replay),
+    Row::Binding(Action::Practice),
+    Row::ResetBindings,
+    Row::DeadZone,
+    Row::ReducedFlashing,
+];
+
+#[derive(Debug, Default)]
+pub struct SettingsMenu {
+    selected: usize,
+    rebinding: bool,
+}
+
+pub enum MenuResult {
+    Open,
+    Closed,
+}
+
+impl SettingsMenu {
+    pub fn update(&mut self, settings: &mut Settings) -> MenuResult {
+        let row = ROWS[self.selected];
+        let mut changed = false;
+        if self.rebinding {
+            if is_key_pressed(KeyCode::Escape) {
+                self.rebinding = false;
+            } else if let Some(key) = get_last_key_pressed().and_then(Key::new)
+                && let Row::Binding(action) = row
+            {
+                settings.bindings.rebind(action, key);
+                self.rebinding = false;
+                changed = true;
+            }
+        } else if is_key_pressed(KeyCode::Escape) {
+            return MenuResult::Closed;
+        } else {
+            if is_key_pressed(KeyCode::Up) {
+                self.selected = (self.selected + ROWS.len() - 1) % ROWS.len();
+            }
+            if is_key_pressed(KeyCode::Down) {
+                self.selected = (self.selected + 1) % ROWS.len();
+            }
+            if is_key_pressed(KeyCode::Enter) {
+                match row {
+                    Row::Binding(_) => self.rebinding = true,
+                    Row::ResetBindings => {
+                        settings.bindings = KeyBindings::default();
+                        changed = true;
+                    }
+                    Row::DeadZone => {}
+                    Row::ReducedFlashing => {
+                        settings.reduced_flashing = !settings.reduced_flashing;
+                        changed = true;
+                    }
+                }
+            }
+            if row == Row::ReducedFlashing
+                && (is_key_pressed(KeyCode::Left) || is_key_pressed(KeyCode::Right))
+            {
+                settings.reduced_flashing = !settings.reduced_flashing;
+                changed = true;
+            }
+            if row == Row::DeadZone {
+                let dead_zone = &mut settings.gamepad.dead_zone;
+                let step = if is_key_pressed(KeyCode::Left) {
+                    -0.05
+                } else if is_key_pressed(KeyCode::Right) {
+                    0.05
+                } else {
+                    0.0
+                };
+                if step != 0.0 {
+                    *dead_zone = (*dead_zone + step).clamp(0.05, 0.95);
+                    changed = true;
+                }
+            }
+        }
+        if changed && let Err(e) = settings.store() {
+            eprintln!("failed to save settings: {e}");
+        }
+        MenuResult::Open
+    }
+
+    pub fn draw<const N: usize, const M: usize>(&self, screen: &Screen<N, M>, settings: &Settings) {
+        screen.blank();
+        screen.draw_text("settings", 3.0 / 4.0);
+        for (index, row) in ROWS.into_iter().enumerate() {
+            let text = match row {
+                Row::Binding(action) if self.rebinding && index == self.selected => {
+                    format!("{}: press a key", action.label())
+                }
+                Row::Binding(action) => {
+                    format!(
+                        "{}: {}",
+                        action.label(),
+                        settings.bindings.key(action).name()
+                    )
+                }
+                Row::ResetBindings => "reset key bindings".into(),
+                Row::DeadZone => format!("stick dead zone: {:.2}", settings.gamepad.dead_zone),
+                Row::ReducedFlashing => format!(
+                    "reduced flashing: {}",
+                    if settings.reduced_flashing {
+                        "on"
+                    } else {
+                        "off"
+                    }
+                ),
+            };
+            let text = if index == self.selected {
+                format!("> {text} <")
+            } else {
+                text
+            };
+            screen.draw_caption(&text, 1.0 / 2.0 - index as f32 / 10.0);
+        }
+        screen.draw_caption(
+            "Up/Down: select - Enter/Left/Right: change - Escape: back",
+            -3.0 / 4.0,
+        );
+    }
+}
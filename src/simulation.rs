@@ -0,0 +1,549 @@
+use std::collections::VecDeque;
+
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::IndexedRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::command::{Command, Flash};
+use crate::level::Attack;
+use crate::playfield::{Coord, Pattern, Transform};
+use crate::script::{Cue, Cues, Style, StyleChange};
+
+pub const TICKS_PER_BEAT: u64 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tick {
+    Countdown(u64),
+    Beat(u64),
+}
+
+impl Tick {
+    pub fn new(ticks: u64, countdown_length: u64) -> Self {
+        if ticks < countdown_length {
+            Tick::Countdown(ticks)
+        } else {
+            Tick::Beat(ticks - countdown_length)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Move {
+    #[serde(rename = "i-")]
+    InnerCcw,
+    #[serde(rename = "i+")]
+    InnerCw,
+    #[serde(rename = "o-")]
+    OuterCcw,
+    #[serde(rename = "o+")]
+    OuterCw,
+}
+
+#[derive(Clone, Debug)]
+pub enum Event<const N: usize, const M: usize> {
+    Tick(Tick),
+    NewAttack(Attack<N, M>, Transform<N, M>),
+    Flash(Pattern<N, M>, Flash),
+    ChangeStyle(StyleChange<N, M>),
+    Death,
+}
+
+pub struct Simulation<const N: usize, const M: usize> {
+    attacks: Vec<Attack<N, M>>,
+    cues: Cues<N, M>,
+    seed: u64,
+    rng: StdRng,
+    player: (Coord<N>, Coord<M>),
+    commands: VecDeque<Command<N, M>>,
+    curr_attack: Option<Attack<N, M>>,
+    curr_transform: Transform<N, M>,
+    pattern: Pattern<N, M>,
+    flash: Flash,
+    flash_tick: u64,
+    flash_ticks: u64,
+    style: Style<N, M>,
+    ticks: u64,
+    dead: bool,
+}
+
+impl<const N: usize, const M: usize> Simulation<N, M> {
+    pub const COUNTDOWN_LENGTH: u64 = 4;
+
+    pub fn new(attacks: Vec<Attack<N, M>>, cues: Cues<N, M>, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self {
+            attacks,
+            cues,
+            seed,
+            player: rng.random(),
+            rng,
+            commands: VecDeque::new(),
+            curr_attack: None,
+            curr_transform: Default::default(),
+            pattern: Pattern::empty(),
+            flash: Flash::Warn,
+            flash_tick: 0,
+            flash_ticks: 1,
+            style: Style::default(),
+            ticks: 0,
+            dead: false,
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn player(&self) -> (Coord<N>, Coord<M>) {
+        self.player
+    }
+
+    pub fn pattern(&self) -> &Pattern<N, M> {
+        &self.pattern
+    }
+
+    pub fn flash(&self) -> Flash {
+        self.flash
+    }
+
+    pub fn flash_progress(&self, tick_fraction: f64) -> f64 {
+        flash_progress(self.ticks, self.flash_tick, self.flash_ticks, tick_fraction)
+    }
+
+    pub fn attack(&self) -> Option<&Attack<N, M>> {
+        self.curr_attack.as_ref()
+    }
+
+    pub fn style(&self) -> &Style<N, M> {
+        &self.style
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.dead
+    }
+
+    pub fn move_player(&mut self, movement: Move) {
+        move_player(&mut self.player, movement);
+    }
+
+    pub fn advance(&mut self) -> Vec<Event<N, M>> {
+        let mut events = Vec::new();
+        if self.dead {
+            return events;
+        }
+        let ticks = self.ticks;
+        self.ticks += 1;
+        if ticks.is_multiple_of(TICKS_PER_BEAT) {
+            events.push(Event::Tick(Tick::new(
+                ticks / TICKS_PER_BEAT,
+                Self::COUNTDOWN_LENGTH,
+            )));
+        }
+        if ticks < Self::COUNTDOWN_LENGTH * TICKS_PER_BEAT {
+            return events;
+        }
+        let beat = ticks / TICKS_PER_BEAT - Self::COUNTDOWN_LENGTH;
+        let on_beat = ticks.is_multiple_of(TICKS_PER_BEAT);
+        if on_beat {
+            self.cue(beat);
+        }
+        // Style changes take effect on their beat, even in the middle of
+        // an attack.
+        while let Some(Command::ChangeStyle(_)) = self.commands.front() {
+            let Some(Command::ChangeStyle(change)) = self.commands.pop_front() else {
+                unreachable!();
+            };
+            self.style.apply(change.clone());
+            events.push(Event::ChangeStyle(change));
+        }
+        if ticks < self.flash_tick + self.flash_ticks {
+            return events;
+        }
+        if self.commands.is_empty()
+            && !self.attacks.is_empty()
+            && on_beat
+            && beat >= self.cues.end()
+        {
+            // Attacks only start on beats that are a multiple of their
+            // length; if none fits, the playfield rests until one does.
+            if let Ok(attack) = self.attacks.choose_weighted(&mut self.rng, |attack| {
+                if beat.is_multiple_of(attack.beat_length()) {
+                    attack.weight(beat)
+                } else {
+                    0.0
+                }
+            }) {
+                let attack = attack.clone();
+                let transform = attack.transform.construct(&mut self.rng, self.player);
+                attack.enqueue(&mut self.commands, transform);
+            }
+        }
+        let (pattern, flash, flash_ticks) = loop {
+            match self.commands.pop_front() {
+                Some(Command::NewAttack(attack, transform)) => {
+                    self.curr_attack = Some(attack.clone());
+                    self.curr_transform = transform;
+                    events.push(Event::NewAttack(attack, transform));
+                }
+                Some(Command::FlashPattern(pattern, flash, ticks)) => {
+                    break (pattern, flash, ticks);
+                }
+                Some(Command::ChangeStyle(change)) => {
+                    self.style.apply(change.clone());
+                    events.push(Event::ChangeStyle(change));
+                }
+                None => break (Pattern::empty(), Flash::Warn, 1),
+            }
+        };
+        self.pattern = pattern.clone();
+        self.flash = flash;
+        self.flash_tick = ticks;
+        self.flash_ticks = flash_ticks;
+        events.push(Event::Flash(pattern, flash));
+        if flash == Flash::Strike && self.pattern[self.player] {
+            self.dead = true;
+            events.push(Event::Death);
+        }
+        events
+    }
+
+    fn cue(&mut self, beat: u64) {
+        let mut changes = Vec::new();
+        for cue in self.cues.at(beat) {
+            match cue {
+                Cue::Attack(attack) => {
+                    let transform = attack.transform.construct(&mut self.rng, self.player);
+                    attack.clone().enqueue(&mut self.commands, transform);
+                }
+                Cue::Style(change) => changes.push(change.clone()),
+            }
+        }
+        for change in changes.into_iter().rev() {
+            self.commands.push_front(Command::ChangeStyle(change));
+        }
+    }
+
+    pub fn run<I>(&mut self, inputs: I) -> Vec<Event<N, M>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[Move]>,
+    {
+        let mut events = Vec::new();
+        for moves in inputs {
+            if self.dead {
+                break;
+            }
+            for &movement in moves.as_ref() {
+                self.move_player(movement);
+            }
+            events.extend(self.advance());
+        }
+        events
+    }
+
+    pub fn into_demonstration(self) -> AttackLoop<N, M> {
+        AttackLoop {
+            flags: Some(self.curr_transform),
+            ..AttackLoop::new(
+                self.curr_attack,
+                self.rng,
+                self.player,
+                AttackLoop::<N, M>::DEMONSTRATION_COUNTDOWN,
+                false,
+            )
+        }
+    }
+}
+
+// Plays one attack over and over, for the demonstration of the attack that
+// ended a game and for practice.
+pub struct AttackLoop<const N: usize, const M: usize> {
+    attack: Option<Attack<N, M>>,
+    // Set to keep the transform flags of every repeat the same, so that only
+    // the origin changes.
+    flags: Option<Transform<N, M>>,
+    rng: StdRng,
+    player: (Coord<N>, Coord<M>),
+    commands: VecDeque<Command<N, M>>,
+    pattern: Pattern<N, M>,
+    flash: Flash,
+    flash_tick: u64,
+    flash_ticks: u64,
+    ticks: u64,
+    countdown_length: u64,
+    counts_deaths: bool,
+    deaths: u64,
+}
+
+impl<const N: usize, const M: usize> AttackLoop<N, M> {
+    pub const DEMONSTRATION_COUNTDOWN: u64 = 8;
+    pub const PRACTICE_COUNTDOWN: u64 = 4;
+
+    fn new(
+        attack: Option<Attack<N, M>>,
+        rng: StdRng,
+        player: (Coord<N>, Coord<M>),
+        countdown_length: u64,
+        counts_deaths: bool,
+    ) -> Self {
+        Self {
+            attack,
+            flags: None,
+            rng,
+            player,
+            commands: VecDeque::new(),
+            pattern: Pattern::empty(),
+            flash: Flash::Warn,
+            flash_tick: 0,
+            flash_ticks: 1,
+            ticks: 0,
+            countdown_length,
+            counts_deaths,
+            deaths: 0,
+        }
+    }
+
+    pub fn practice(attack: Attack<N, M>, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let player = rng.random();
+        Self::new(Some(attack), rng, player, Self::PRACTICE_COUNTDOWN, true)
+    }
+
+    pub fn attack(&self) -> Option<&Attack<N, M>> {
+        self.attack.as_ref()
+    }
+
+    pub fn player(&self) -> (Coord<N>, Coord<M>) {
+        self.player
+    }
+
+    pub fn pattern(&self) -> &Pattern<N, M> {
+        &self.pattern
+    }
+
+    pub fn flash(&self) -> Flash {
+        self.flash
+    }
+
+    pub fn flash_progress(&self, tick_fraction: f64) -> f64 {
+        flash_progress(self.ticks, self.flash_tick, self.flash_ticks, tick_fraction)
+    }
+
+    pub fn countdown_length(&self) -> u64 {
+        self.countdown_length
+    }
+
+    pub fn deaths(&self) -> u64 {
+        self.deaths
+    }
+
+    pub fn move_player(&mut self, movement: Move) {
+        move_player(&mut self.player, movement);
+    }
+
+    pub fn advance(&mut self) -> Vec<Event<N, M>> {
+        let mut events = Vec::new();
+        let Some(attack) = &self.attack else {
+            return events;
+        };
+        let ticks = self.ticks;
+        self.ticks += 1;
+        if ticks.is_multiple_of(TICKS_PER_BEAT) {
+            events.push(Event::Tick(Tick::new(
+                ticks / TICKS_PER_BEAT,
+                self.countdown_length,
+            )));
+        }
+        if ticks < self.countdown_length * TICKS_PER_BEAT
+            || ticks < self.flash_tick + self.flash_ticks
+        {
+            return events;
+        }
+        if self.commands.is_empty() {
+            let transform = attack.transform.construct(&mut self.rng, self.player);
+            let transform = match self.flags {
+                Some(flags) => Transform {
+                    origin: transform.origin,
+                    ..flags
+                },
+                None => transform,
+            };
+            attack.clone().enqueue(&mut self.commands, transform);
+        }
+        while let Some(command) = self.commands.pop_front() {
+            match command {
+                Command::NewAttack(attack, transform) => {
+                    events.push(Event::NewAttack(attack, transform));
+                }
+                Command::FlashPattern(pattern, flash, flash_ticks) => {
+                    self.pattern = pattern.clone();
+                    self.flash = flash;
+                    self.flash_tick = ticks;
+                    self.flash_ticks = flash_ticks;
+                    events.push(Event::Flash(pattern, flash));
+                    // Getting hit is counted, but the attack keeps going.
+                    if self.counts_deaths && flash == Flash::Strike && self.pattern[self.player] {
+                        self.deaths += 1;
+                        events.push(Event::Death);
+                    }
+                    break;
+                }
+                Command::ChangeStyle(_) => {}
+            }
+        }
+        events
+    }
+}
+
+fn move_player<const N: usize, const M: usize>((i, o): &mut (Coord<N>, Coord<M>), movement: Move) {
+    match movement {
+        Move::InnerCcw => *i = *i - Coord::ONE,
+        Move::InnerCw => *i = *i + Coord::ONE,
+        Move::OuterCcw => *o = *o - Coord::ONE,
+        Move::OuterCw => *o = *o + Coord::ONE,
+    }
+}
+
+fn flash_progress(ticks: u64, flash_tick: u64, flash_ticks: u64, tick_fraction: f64) -> f64 {
+    // `ticks` has already moved past the tick that is currently playing.
+    let elapsed = ticks.saturating_sub(flash_tick + 1) as f64 + tick_fraction;
+    elapsed / flash_ticks as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::iter;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn attack(value: serde_json::Value) -> Attack<6, 6> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn run(attacks: Vec<Attack<6, 6>>, beats: u64) -> (Simulation<6, 6>, Vec<Event<6, 6>>) {
+        let mut simulation = Simulation::new(attacks, Cues::default(), 7);
+        let events = simulation.run(iter::repeat_n([], (beats * TICKS_PER_BEAT) as usize));
+        (simulation, events)
+    }
+
+    fn new_attack_beats(events: &[Event<6, 6>]) -> Vec<u64> {
+        let mut beat = None;
+        let mut beats = Vec::new();
+        for event in events {
+            match event {
+                Event::Tick(Tick::Beat(b)) => beat = Some(*b),
+                Event::NewAttack(..) => beats.push(beat.unwrap()),
+                _ => {}
+            }
+        }
+        beats
+    }
+
+    #[test]
+    fn strike_on_player_is_fatal() {
+        let everywhere = attack(json!({
+            "name": "everywhere",
+            "patterns": {"A": "all"},
+            "timeline": ["warn A", "strike A"],
+        }));
+        let (simulation, events) = run(vec![everywhere], 20);
+        assert!(simulation.is_dead());
+        assert!(matches!(events.last(), Some(Event::Death)));
+        assert_eq!(
+            events
+                .iter()
+                .filter(|event| matches!(event, Event::Death))
+                .count(),
+            1
+        );
+        // The attack starts right after the countdown and strikes a beat
+        // later, and nothing happens after the death.
+        assert_eq!(new_attack_beats(&events), [0]);
+        assert_eq!(
+            simulation.ticks(),
+            (Simulation::<6, 6>::COUNTDOWN_LENGTH + 1) * TICKS_PER_BEAT + 1
+        );
+        assert_eq!(simulation.attack().unwrap().name, "everywhere");
+    }
+
+    #[test]
+    fn attacks_wait_for_their_beat() {
+        let nothing = attack(json!({
+            "patterns": {"A": "none"},
+            "timeline": ["warn A", "strike A", "rest"],
+            "min_beat": 1,
+        }));
+        let (simulation, events) = run(vec![nothing], 20);
+        assert!(!simulation.is_dead());
+        assert!(!events.iter().any(|event| matches!(event, Event::Death)));
+        // A three beat attack only starts on multiples of three, and not
+        // before its minimum beat.
+        let beats = new_attack_beats(&events);
+        assert_eq!(beats, [3, 6, 9, 12, 15]);
+        // The same seed plays out the same way.
+        let (_, again) = run(simulation.attacks.clone(), 20);
+        assert_eq!(format!("{again:?}"), format!("{events:?}"));
+    }
+
+    fn repeat(attack_loop: &mut AttackLoop<6, 6>, beats: u64) -> Vec<Event<6, 6>> {
+        (0..beats * TICKS_PER_BEAT)
+            .flat_map(|_| attack_loop.advance())
+            .collect()
+    }
+
+    #[test]
+    fn practice_counts_deaths_and_keeps_going() {
+        let everywhere = attack(json!({
+            "patterns": {"A": "all"},
+            "timeline": ["warn A", "strike A"],
+        }));
+        let mut practice = AttackLoop::practice(everywhere, 7);
+        let events = repeat(&mut practice, 20);
+        let count = |f: fn(&Event<6, 6>) -> bool| events.iter().filter(|event| f(event)).count();
+        assert_eq!(count(|event| matches!(event, Event::Death)), 8);
+        assert_eq!(count(|event| matches!(event, Event::NewAttack(..))), 8);
+        assert_eq!(practice.deaths(), 8);
+    }
+
+    #[test]
+    fn demonstration_keeps_the_transform_flags() {
+        let mirrored = attack(json!({
+            "patterns": {"A": "all"},
+            "timeline": ["warn A", "strike A"],
+            "mirror": true,
+            "transpose": true,
+        }));
+        let (simulation, _) = run(vec![mirrored], 20);
+        let flags = simulation.curr_transform;
+        let mut demonstration = simulation.into_demonstration();
+        assert_eq!(
+            demonstration.countdown_length(),
+            AttackLoop::<6, 6>::DEMONSTRATION_COUNTDOWN
+        );
+        let events = repeat(&mut demonstration, 40);
+        let mut attacks = 0;
+        for event in &events {
+            match event {
+                Event::NewAttack(_, transform) => {
+                    assert_eq!(
+                        Transform {
+                            origin: flags.origin,
+                            ..*transform
+                        },
+                        flags
+                    );
+                    attacks += 1;
+                }
+                Event::Death => panic!("the demonstration doesn't count deaths"),
+                _ => {}
+            }
+        }
+        assert_eq!(attacks, 16);
+        assert_eq!(demonstration.deaths(), 0);
+    }
+}